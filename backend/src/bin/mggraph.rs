@@ -0,0 +1,449 @@
+// src/bin/mggraph.rs
+use clap::{Parser as ClapParser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use mggraph_backend::calculator::{
+    Calculate, GrammarSizeCalculator, DEFAULT_ALPHABET_SIZE, DEFAULT_NUM_TYPES,
+};
+use mggraph_backend::logging::{self, LogFormat};
+use mggraph_backend::parse::{
+    decomp::Decomposer,
+    export::Exporter,
+    grammar::Grammar,
+    import::{GrammarFormat, Importer},
+    lint::{LintWarning, Linter, DEFAULT_START_CATEGORY},
+    local_graph::LocalGraph,
+    mg::{LexicalItem, MG},
+    parser::Parser,
+};
+
+/* Headless grammar analysis, reading grammars in any importable format. */
+#[derive(ClapParser)]
+#[command(
+    name = "mggraph",
+    version,
+    about = "Analyse Minimalist Grammars from the command line"
+)]
+struct Cli {
+    /// How results are printed
+    #[arg(long, short, value_enum, global = true, default_value_t = Output::Table)]
+    output: Output,
+
+    /// The format of the grammar files, detected if not given
+    #[arg(long, value_enum, global = true)]
+    input_format: Option<GrammarFormat>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Calculate the MDL size of one or more grammars
+    Size {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[command(flatten)]
+        size: SizeArgs,
+    },
+    /// Check grammars for well-formedness problems
+    Lint {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[arg(long, default_value = DEFAULT_START_CATEGORY)]
+        start: String,
+        /// Exit with an error if there are any warnings
+        #[arg(long)]
+        deny_warnings: bool,
+    },
+    /// Print the parsed lexical items of a grammar
+    Parse { file: PathBuf },
+    /// Suggest affixes which could be decomposed out of a grammar
+    DecomposeSuggest { file: PathBuf },
+    /// Print the state graph of a grammar, without needing Neo4j
+    ExportGraph {
+        file: PathBuf,
+        #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+        format: GraphFormat,
+    },
+    /// Compare the sizes of grammar variants against the first
+    Compare {
+        #[arg(required = true, num_args = 2..)]
+        files: Vec<PathBuf>,
+        #[command(flatten)]
+        size: SizeArgs,
+    },
+}
+
+#[derive(clap::Args)]
+struct SizeArgs {
+    #[arg(long, default_value_t = DEFAULT_ALPHABET_SIZE)]
+    alphabet_size: usize,
+    #[arg(long, default_value_t = DEFAULT_NUM_TYPES)]
+    num_types: usize,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+enum Output {
+    Table,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum GraphFormat {
+    Dot,
+    Json,
+}
+
+/* A grammar file converted to the native format and parsed. */
+struct GrammarFile {
+    name: String,
+    native: String,
+    items: Vec<LexicalItem>,
+}
+
+fn read_grammar(path: &Path, format: Option<GrammarFormat>) -> Result<GrammarFile, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let (_format, native, items) =
+        Importer::import(&text, format).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(GrammarFile {
+        name: path.display().to_string(),
+        native,
+        items,
+    })
+}
+
+#[derive(Serialize)]
+struct SizeRow {
+    file: String,
+    n_items: usize,
+    n_features: usize,
+    n_phonemes: usize,
+    mdl: f64,
+    // compared with the first grammar
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mdl_change: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    added: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    removed: Option<Vec<String>>,
+}
+
+fn get_size_row(grammar: &GrammarFile, size: &SizeArgs) -> Result<SizeRow, Box<dyn Error>> {
+    let parsed = Grammar::new(&grammar.native, size.alphabet_size, size.num_types)
+        .map_err(|e| format!("{}: {}", grammar.name, e))?;
+    let mdl = GrammarSizeCalculator.get_grammar_size(&parsed);
+    Ok(SizeRow {
+        file: grammar.name.clone(),
+        n_items: grammar.items.len(),
+        n_features: mdl.n_features,
+        n_phonemes: mdl.n_phonemes,
+        mdl: mdl.mdl,
+        mdl_change: None,
+        added: None,
+        removed: None,
+    })
+}
+
+/* Items as written, for comparing grammars regardless of order or comments. */
+fn get_item_lines(items: &[LexicalItem]) -> Vec<String> {
+    items
+        .iter()
+        .map(|li| {
+            Exporter::to_native(std::slice::from_ref(li))
+                .trim()
+                .to_string()
+        })
+        .collect()
+}
+
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .enumerate()
+            .map(|(i, c)| format!("{:<width$}", c, width = widths[i]))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!("{}", format_row(headers.to_vec()));
+    println!(
+        "{}",
+        format_row(
+            widths
+                .iter()
+                .map(|w| "-".repeat(*w))
+                .collect::<Vec<String>>()
+                .iter()
+                .map(|s| s.as_str())
+                .collect()
+        )
+    );
+    for row in rows {
+        println!("{}", format_row(row.iter().map(|s| s.as_str()).collect()));
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn print_sizes(rows: &[SizeRow], output: Output) -> Result<(), Box<dyn Error>> {
+    if output == Output::Json {
+        return print_json(&rows);
+    }
+    let is_comparison = rows.iter().any(|r| r.mdl_change.is_some());
+    let mut headers = vec!["file", "items", "features", "phonemes", "mdl"];
+    if is_comparison {
+        headers.extend(["change", "added", "removed"]);
+    }
+    let table: Vec<Vec<String>> = rows
+        .iter()
+        .map(|r| {
+            let mut row = vec![
+                r.file.clone(),
+                r.n_items.to_string(),
+                r.n_features.to_string(),
+                r.n_phonemes.to_string(),
+                format!("{:.2}", r.mdl),
+            ];
+            if is_comparison {
+                row.push(
+                    r.mdl_change
+                        .map(|c| format!("{:+.2}", c))
+                        .unwrap_or_default(),
+                );
+                row.push(
+                    r.added
+                        .as_ref()
+                        .map(|a| a.len().to_string())
+                        .unwrap_or_default(),
+                );
+                row.push(
+                    r.removed
+                        .as_ref()
+                        .map(|a| a.len().to_string())
+                        .unwrap_or_default(),
+                );
+            }
+            row
+        })
+        .collect();
+    print_table(&headers, &table);
+    Ok(())
+}
+
+async fn run(cli: Cli) -> Result<ExitCode, Box<dyn Error>> {
+    let input_format = cli.input_format;
+    let read = |path: &PathBuf| read_grammar(path, input_format);
+
+    match cli.command {
+        Command::Size { files, size } => {
+            let rows = files
+                .iter()
+                .map(|f| get_size_row(&read(f)?, &size))
+                .collect::<Result<Vec<SizeRow>, Box<dyn Error>>>()?;
+            print_sizes(&rows, cli.output)?;
+        }
+
+        Command::Compare { files, size } => {
+            let grammars = files
+                .iter()
+                .map(read)
+                .collect::<Result<Vec<GrammarFile>, Box<dyn Error>>>()?;
+            let baseline = get_size_row(&grammars[0], &size)?;
+            let baseline_items = get_item_lines(&grammars[0].items);
+
+            let mut rows = Vec::new();
+            for grammar in grammars.iter().skip(1) {
+                let mut row = get_size_row(grammar, &size)?;
+                let items = get_item_lines(&grammar.items);
+                row.mdl_change = Some(row.mdl - baseline.mdl);
+                row.added = Some(
+                    items
+                        .iter()
+                        .filter(|i| !baseline_items.contains(i))
+                        .cloned()
+                        .collect(),
+                );
+                row.removed = Some(
+                    baseline_items
+                        .iter()
+                        .filter(|i| !items.contains(i))
+                        .cloned()
+                        .collect(),
+                );
+                rows.push(row);
+            }
+            rows.insert(0, baseline);
+            print_sizes(&rows, cli.output)?;
+        }
+
+        Command::Lint {
+            files,
+            start,
+            deny_warnings,
+        } => {
+            // in the order given, keeping files which share a name
+            let mut all_warnings: Vec<(String, Vec<LintWarning>)> = Vec::new();
+            for file in files.iter() {
+                let grammar = read(file)?;
+                all_warnings.push((grammar.name, Linter::new(&start).lint(&grammar.items)));
+            }
+
+            if cli.output == Output::Json {
+                print_json(&all_warnings)?;
+            } else {
+                let table: Vec<Vec<String>> = all_warnings
+                    .iter()
+                    .flat_map(|(file, warnings)| {
+                        warnings.iter().map(move |w| {
+                            vec![
+                                file.clone(),
+                                w.morph.clone(),
+                                format!("{:?}", w.kind),
+                                w.message.clone(),
+                            ]
+                        })
+                    })
+                    .collect();
+                print_table(&["file", "item", "warning", "message"], &table);
+            }
+
+            if deny_warnings && all_warnings.iter().any(|(_, w)| !w.is_empty()) {
+                return Ok(ExitCode::from(2));
+            }
+        }
+
+        Command::Parse { file } => {
+            let grammar = read(&file)?;
+            if cli.output == Output::Json {
+                print_json(&grammar.items)?;
+            } else {
+                let table: Vec<Vec<String>> = grammar
+                    .items
+                    .iter()
+                    .map(|li| {
+                        vec![
+                            li.morph.clone(),
+                            li.bundle
+                                .iter()
+                                .map(|f| f.raw.clone())
+                                .collect::<Vec<String>>()
+                                .join(" "),
+                            li.bundle
+                                .iter()
+                                .map(|f| f.rel.get_feature_type())
+                                .collect::<Vec<&str>>()
+                                .join(" "),
+                        ]
+                    })
+                    .collect();
+                print_table(&["morph", "features", "types"], &table);
+            }
+        }
+
+        Command::DecomposeSuggest { file } => {
+            let grammar = read(&file)?;
+            let suggestions: BTreeMap<String, Vec<String>> = Decomposer::new()
+                .get_decompose_suggestions(&grammar.items)
+                .into_iter()
+                .map(|(affix, lis)| {
+                    (
+                        affix,
+                        lis.iter()
+                            .map(|i| grammar.items[*i].morph.clone())
+                            .collect(),
+                    )
+                })
+                .collect();
+
+            if cli.output == Output::Json {
+                print_json(&suggestions)?;
+            } else {
+                let table: Vec<Vec<String>> = suggestions
+                    .iter()
+                    .map(|(affix, morphs)| vec![affix.clone(), morphs.join(", ")])
+                    .collect();
+                print_table(&["affix", "items"], &table);
+            }
+        }
+
+        Command::ExportGraph { file, format } => {
+            let grammar = read(&file)?;
+            let mut mg = MG::new();
+            mg.update_grammar(grammar.items);
+
+            let graph = LocalGraph::new();
+            Parser::convert_stored_to_graph(&mut mg, &graph).await?;
+            match format {
+                GraphFormat::Dot => print!("{}", graph.to_dot()),
+                GraphFormat::Json => print_json(&graph.snapshot())?,
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    // only warnings, e.g skipped annotations, on stderr so piped output stays clean
+    let level = std::env::var("MGGRAPH_LOG").unwrap_or_else(|_| String::from("warn"));
+    if let Err(e) = logging::init(&level, LogFormat::Text, None, true) {
+        eprintln!("error: {}", e);
+        return ExitCode::FAILURE;
+    }
+    match run(Cli::parse()).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_are_required() {
+        for command in ["size", "lint", "compare"] {
+            assert!(Cli::try_parse_from(["mggraph", command]).is_err());
+        }
+        // a variant needs something to be compared against
+        assert!(Cli::try_parse_from(["mggraph", "compare", "a.mg"]).is_err());
+        assert!(Cli::try_parse_from(["mggraph", "size", "a.mg", "b.mg"]).is_ok());
+    }
+
+    #[test]
+    fn output_options_are_global() {
+        let cli = Cli::try_parse_from(["mggraph", "lint", "a.mg", "-o", "json"]).unwrap();
+        assert!(cli.output == Output::Json);
+        assert!(
+            matches!(cli.command, Command::Lint { start, .. } if start == DEFAULT_START_CATEGORY)
+        );
+    }
+
+    #[test]
+    fn input_formats_are_the_importer_formats() {
+        let cli =
+            Cli::try_parse_from(["mggraph", "parse", "a.pl", "--input-format", "prolog"]).unwrap();
+        assert_eq!(cli.input_format, Some(GrammarFormat::Prolog));
+        assert!(Cli::try_parse_from(["mggraph", "parse", "a", "--input-format", "xml"]).is_err());
+    }
+}
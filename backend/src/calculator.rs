@@ -1,27 +1,55 @@
 use crate::parse::grammar::Grammar;
+use crate::parse::mg::is_empty_morph;
+use tracing::{debug, trace};
+use utoipa::ToSchema;
 
-#[derive(serde::Serialize)]
+// silent heads (ε) have no phonological content,
+// so they are encoded by their features alone
+const SILENT_PHON_COST: usize = 0;
+
+// defaults used by the UI, for recording the MDL of saved MGs
+pub const DEFAULT_ALPHABET_SIZE: usize = 26;
+pub const DEFAULT_NUM_TYPES: usize = 7;
+
+/* Grammars are saved even if they can't be sized, just without an MDL. */
+pub fn get_saved_mdl(grammar: &[String]) -> Option<f64> {
+    let grammar = Grammar::new(
+        &grammar.join("\n"),
+        DEFAULT_ALPHABET_SIZE,
+        DEFAULT_NUM_TYPES,
+    )
+    .ok()?;
+    Some(GrammarSizeCalculator.get_grammar_size(&grammar).mdl)
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(serde::Serialize, ToSchema)]
 pub struct MDL {
-    n_features: usize,
-    n_phonemes: usize,
-    encoding_cost_per_symbol: f64,
-    mdl: f64
+    pub n_features: usize,
+    pub n_phonemes: usize,
+    pub encoding_cost_per_symbol: f64,
+    pub mdl: f64,
 }
 
 pub trait Calculate {
-    fn get_grammar_size(&self, mg: &Grammar, verbose: bool) -> MDL;
+    fn get_grammar_size(&self, mg: &Grammar) -> MDL;
 }
 
 pub struct GrammarSizeCalculator;
 
 impl Calculate for GrammarSizeCalculator {
-    fn get_grammar_size(&self, mg: &Grammar, verbose: bool) -> MDL {
+    fn get_grammar_size(&self, mg: &Grammar) -> MDL {
         let mut n_symbols: f64 = 0.0;
         let mut n_features: usize = 0;
         let mut n_phonemes: usize = 0;
 
         for (phon, feature_bundle) in mg.set_phon.iter().zip(mg.set_feature_bundles.iter()) {
-            let n_phonemes_i = phon.len(); // number of characters in the phonological representation
+            // number of characters in the phonological representation
+            let n_phonemes_i = if is_empty_morph(phon) {
+                SILENT_PHON_COST
+            } else {
+                phon.len()
+            };
             let n_features_i = feature_bundle.len();
 
             n_features += n_features_i;
@@ -30,18 +58,21 @@ impl Calculate for GrammarSizeCalculator {
             let n_symbols_i = n_phonemes_i + 2 * n_features_i + 1;
             n_symbols += n_symbols_i as f64;
 
-            if verbose {
-                println!("Word: {}", phon);
-                feature_bundle.iter().for_each(|f| print!(" {}",f));
-                println!("(|{}| + 2 * |{}| + 1)", n_phonemes_i,n_features_i);
-            }
+            trace!(
+                phon = %phon,
+                features = %feature_bundle.join(" "),
+                "{} + 2 * {} + 1 symbols",
+                n_phonemes_i,
+                n_features_i
+            );
         }
 
-        let encoding_cost_per_symbol: f64 = ((mg.alphabet_size + mg.n_feature_types + mg.get_base_size() + 1) as f64).log2();
-        if verbose {
-            println!("Base Size: {}", mg.get_base_size());
-            println!("Encoding Cost Per Symbol: {}", encoding_cost_per_symbol);
-        }
+        let encoding_cost_per_symbol: f64 =
+            ((mg.alphabet_size + mg.get_n_feature_types() + mg.get_base_size() + 1) as f64).log2();
+        debug!(
+            base_size = mg.get_base_size(),
+            encoding_cost_per_symbol, "Sized grammar"
+        );
 
         let mdl = n_symbols * encoding_cost_per_symbol;
 
@@ -49,8 +80,7 @@ impl Calculate for GrammarSizeCalculator {
             n_features,
             n_phonemes,
             encoding_cost_per_symbol,
-            mdl
+            mdl,
         }
-
     }
-}
\ No newline at end of file
+}
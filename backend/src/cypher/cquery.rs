@@ -1,7 +1,7 @@
-use serde_json::Value;
 use core::panic;
-use std::error::Error;
+use serde_json::Value;
 use std::collections::HashMap;
+use std::error::Error;
 
 #[derive(Clone)]
 pub struct CQuery {
@@ -12,7 +12,7 @@ pub struct CQuery {
 
 #[derive(Clone)]
 pub struct CQueryStorage {
-    pub queries: HashMap<String, CQuery>
+    pub queries: HashMap<String, CQuery>,
 }
 
 #[derive(Clone)]
@@ -32,20 +32,27 @@ pub struct Relationship {
 
 const QUERIES_JSON: &str = include_str!("queries.json");
 
-impl CQueryStorage {
+impl Default for CQueryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+impl CQueryStorage {
     pub fn new() -> Self {
         match load_queries_from_json(QUERIES_JSON) {
             Ok(queries) => Self { queries },
             Err(e) => {
-                eprintln!("Queries could not be loaded. Ensure the queries JSON file is available and formatted correctly");
+                tracing::error!("Queries could not be loaded. Ensure the queries JSON file is available and formatted correctly");
                 panic!("Error: {}", e);
             }
         }
     }
 
     pub fn get_query(&self, q_id: &str) -> &CQuery {
-        self.queries.get(q_id).unwrap_or_else(|| panic!("No Query Available For ID {}", q_id))
+        self.queries
+            .get(q_id)
+            .unwrap_or_else(|| panic!("No Query Available For ID {}", q_id))
     }
 
     pub fn get_remove_redundant_nodes(&self) -> &CQuery {
@@ -58,16 +65,27 @@ impl CQueryStorage {
         self.get_query(Q_ID)
     }
 
+    pub fn get_graph_nodes(&self) -> &CQuery {
+        const Q_ID: &str = "get_graph_nodes";
+        self.get_query(Q_ID)
+    }
+
+    pub fn get_graph_edges(&self) -> &CQuery {
+        const Q_ID: &str = "get_graph_edges";
+        self.get_query(Q_ID)
+    }
+
     pub fn get_create_node(&self, n: Node) -> CQuery {
         const Q_ID: &str = "create_node";
         let q = self.get_query(Q_ID);
 
-        CQuery { 
+        CQuery {
             name: q.name.clone(),
-            query: q.query
+            query: q
+                .query
                 .replace("{TYPE}", n.state_type.as_str())
-                .replace("{NODE_LABEL_VAL}", n.label.as_str()),
-            desc: q.desc.clone()
+                .replace("{NODE_LABEL_VAL}", &escape(n.label.as_str())),
+            desc: q.desc.clone(),
         }
     }
 
@@ -76,127 +94,168 @@ impl CQueryStorage {
         const Q_ID: &str = "delete_node";
         let q = self.get_query(Q_ID);
 
-        CQuery { 
+        CQuery {
             name: q.name.clone(),
-            query: q.query
+            query: q
+                .query
                 .replace("{TYPE}", n.state_type.as_str())
                 .replace("{NODE_LABEL_KEY}", "name")
-                .replace("{NODE_LABEL_VAL}", n.label.as_str()),
-            desc: q.desc.clone()
+                .replace("{NODE_LABEL_VAL}", &escape(n.label.as_str())),
+            desc: q.desc.clone(),
         }
     }
 
     pub fn get_contract_edge(&self, node_a_label: &str, node_b_label: &str) -> CQuery {
         const Q_ID: &str = "contract_edge";
         let q = self.get_query(Q_ID);
-        CQuery { 
+        CQuery {
             name: q.name.clone(),
-            query: q.query
-                .replace("{NODE_A_LABEL_VAL}", node_a_label)
-                .replace("{NODE_B_LABEL_VAL}", node_b_label),
-            desc: q.desc.clone()
+            query: q
+                .query
+                .replace("{NODE_A_LABEL_VAL}", &escape(node_a_label))
+                .replace("{NODE_B_LABEL_VAL}", &escape(node_b_label)),
+            desc: q.desc.clone(),
         }
     }
 
-    pub fn get_switch_edge_origin(&self, node_label_prev_origin: &str, node_label_new_origin: &str) -> CQuery {
+    pub fn get_switch_edge_origin(
+        &self,
+        node_label_prev_origin: &str,
+        node_label_new_origin: &str,
+    ) -> CQuery {
         const Q_ID: &str = "switch_edge_origin";
         let q = self.get_query(Q_ID);
-        CQuery { 
+        CQuery {
             name: q.name.clone(),
-            query: q.query
-                .replace("{NODE_LABEL_PREV_ORIGIN}", node_label_prev_origin)
-                .replace("{NODE_LABEL_NEW_ORIGIN}", node_label_new_origin),
-            desc: q.desc.clone()
+            query: q
+                .query
+                .replace("{NODE_LABEL_PREV_ORIGIN}", &escape(node_label_prev_origin))
+                .replace("{NODE_LABEL_NEW_ORIGIN}", &escape(node_label_new_origin)),
+            desc: q.desc.clone(),
         }
     }
 
-    pub fn get_switch_edge_endpoint(&self, node_label_prev_endpoint: &str, node_label_new_endpoint: &str) -> CQuery {
+    pub fn get_switch_edge_endpoint(
+        &self,
+        node_label_prev_endpoint: &str,
+        node_label_new_endpoint: &str,
+    ) -> CQuery {
         const Q_ID: &str = "switch_edge_endpoint";
         let q = self.get_query(Q_ID);
-        CQuery { 
+        CQuery {
             name: q.name.clone(),
-            query: q.query
-                .replace("{NODE_LABEL_PREV_ENDPOINT}", node_label_prev_endpoint)
-                .replace("{NODE_LABEL_NEW_ENDPOINT}", node_label_new_endpoint),
-            desc: q.desc.clone()
+            query: q
+                .query
+                .replace(
+                    "{NODE_LABEL_PREV_ENDPOINT}",
+                    &escape(node_label_prev_endpoint),
+                )
+                .replace(
+                    "{NODE_LABEL_NEW_ENDPOINT}",
+                    &escape(node_label_new_endpoint),
+                ),
+            desc: q.desc.clone(),
         }
     }
 
-    pub fn get_set_node_property(&self, state_type: &str, node_label_key: &str, node_label_val: &str, property_key: &str, property_val: &str) -> CQuery {
+    pub fn get_set_node_property(
+        &self,
+        state_type: &str,
+        node_label_key: &str,
+        node_label_val: &str,
+        property_key: &str,
+        property_val: &str,
+    ) -> CQuery {
         const Q_ID: &str = "set_node_property";
         let q = self.get_query(Q_ID);
 
-        CQuery { 
+        CQuery {
             name: q.name.clone(),
-            query: q.query
+            query: q
+                .query
                 .replace("{TYPE}", state_type)
                 .replace("{NODE_LABEL_KEY}", node_label_key)
-                .replace("{NODE_LABEL_VAL}", node_label_val)
+                .replace("{NODE_LABEL_VAL}", &escape(node_label_val))
                 .replace("{PROPERTY_KEY}", property_key)
-                .replace("{PROPERTY_VAL}", property_val),
-            desc: q.desc.clone()
+                .replace("{PROPERTY_VAL}", &escape(property_val)),
+            desc: q.desc.clone(),
         }
     }
 
-    pub fn get_set_relationship_property(&self, rel_id: &str, rel_val: &str, prop_key: &str, prop_val: &str) -> CQuery {
+    pub fn get_set_relationship_property(
+        &self,
+        rel_id: &str,
+        rel_val: &str,
+        prop_key: &str,
+        prop_val: &str,
+    ) -> CQuery {
         const Q_ID: &str = "set_relationship_property";
         let q = self.get_query(Q_ID);
 
-        CQuery { 
+        CQuery {
             name: q.name.clone(),
-            query: q.query
+            query: q
+                .query
                 .replace("{REL_ID}", rel_id)
-                .replace("{REL_VAL}", rel_val)
+                .replace("{REL_VAL}", &escape(rel_val))
                 .replace("{PROPERTY_KEY}", prop_key)
-                .replace("{PROPERTY_VAL}", prop_val),
-            desc: q.desc.clone()
+                .replace("{PROPERTY_VAL}", &escape(prop_val)),
+            desc: q.desc.clone(),
         }
     }
 
     pub fn get_set_relationship(&self, rel: Relationship) -> CQuery {
-
-        
-
         const Q_ID: &str = "set_relationship";
         let q = self.get_query(Q_ID);
 
         // TODO: set props on relationshop
-        CQuery { 
+        CQuery {
             name: q.name.clone(),
-            query: q.query
+            query: q
+                .query
                 .replace("{NODE_A_TYPE}", rel.node_a.state_type.as_str())
                 .replace("{NODE_A_LABEL_KEY}", "name")
-                .replace("{NODE_A_LABEL_VAL}", rel.node_a.label.as_str())
+                .replace("{NODE_A_LABEL_VAL}", &escape(rel.node_a.label.as_str()))
                 .replace("{NODE_B_TYPE}", rel.node_b.state_type.as_str())
                 .replace("{NODE_B_LABEL_KEY}", "name")
-                .replace("{NODE_B_LABEL_VAL}", rel.node_b.label.as_str())
+                .replace("{NODE_B_LABEL_VAL}", &escape(rel.node_b.label.as_str()))
                 .replace("{REL_TYPE}", "Merge")
                 .replace("{PROPERTY_KEY}", "li")
-                .replace("{PROPERTY_VAL}", rel.li.as_str()),
-            desc: q.desc.clone()
+                .replace("{PROPERTY_VAL}", &escape(rel.li.as_str())),
+            desc: q.desc.clone(),
         }
     }
 
-    pub fn get_delete_relationship(&self, node_a_type: &str, node_a_label_key: &str, node_a_label_val: &str, 
-                                node_b_type: &str, node_b_label_key: &str, node_b_label_val: &str,
-                                type_rel: &str, prop_key: &str, prop_val: &str) -> CQuery {
-
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_delete_relationship(
+        &self,
+        node_a_type: &str,
+        node_a_label_key: &str,
+        node_a_label_val: &str,
+        node_b_type: &str,
+        node_b_label_key: &str,
+        node_b_label_val: &str,
+        type_rel: &str,
+        prop_key: &str,
+        prop_val: &str,
+    ) -> CQuery {
         const Q_ID: &str = "delete_relationship";
         let q = self.get_query(Q_ID);
 
-        CQuery { 
+        CQuery {
             name: q.name.clone(),
-            query: q.query
+            query: q
+                .query
                 .replace("{NODE_A_TYPE}", node_a_type)
                 .replace("{NODE_A_LABEL_KEY}", node_a_label_key)
-                .replace("{NODE_A_LABEL_VAL}", node_a_label_val)
+                .replace("{NODE_A_LABEL_VAL}", &escape(node_a_label_val))
                 .replace("{NODE_B_TYPE}", node_b_type)
                 .replace("{NODE_B_LABEL_KEY}", node_b_label_key)
-                .replace("{NODE_B_LABEL_VAL}", node_b_label_val)
+                .replace("{NODE_B_LABEL_VAL}", &escape(node_b_label_val))
                 .replace("{REL_TYPE}", type_rel)
                 .replace("{PROPERTY_KEY}", prop_key)
-                .replace("{PROPERTY_VAL}", prop_val),
-            desc: q.desc.clone()
+                .replace("{PROPERTY_VAL}", &escape(prop_val)),
+            desc: q.desc.clone(),
         }
     }
 
@@ -206,10 +265,11 @@ impl CQueryStorage {
 
         CQuery {
             name: q.name.clone(),
-            query: q.query
-                .replace("{START_STATE}", start_state)
-                .replace("{END_STATE}", end_state),
-            desc: q.desc.clone()
+            query: q
+                .query
+                .replace("{START_STATE}", &escape(start_state))
+                .replace("{END_STATE}", &escape(end_state)),
+            desc: q.desc.clone(),
         }
     }
 
@@ -219,13 +279,25 @@ impl CQueryStorage {
 
         CQuery {
             name: q.name.clone(),
-            query: q.query
-                .replace("{START_STATE}", start_state)
-                .replace("{END_STATE}", end_state),
-            desc: q.desc.clone()
+            query: q
+                .query
+                .replace("{START_STATE}", &escape(start_state))
+                .replace("{END_STATE}", &escape(end_state)),
+            desc: q.desc.clone(),
         }
     }
+}
 
+/*
+Escapes a value for a quoted Cypher string, so that grammar text such as
+a morph ending in a backslash can't change the query. Works inside both
+single and double quotes.
+*/
+pub fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\'', "\\'")
 }
 
 pub fn load_queries_from_json(queries: &str) -> Result<HashMap<String, CQuery>, Box<dyn Error>> {
@@ -239,22 +311,42 @@ pub fn load_queries_from_json(queries: &str) -> Result<HashMap<String, CQuery>,
 
     if let Some(query_map) = json.get("queries").and_then(|q| q.as_object()) {
         for (q_id, value) in query_map {
-
             if let (Some(name), Some(query), Some(desc)) = (
                 value.get("name").and_then(|v| v.as_str()),
                 value.get("query").and_then(|v| v.as_str()),
-                value.get("desc").and_then(|v| v.as_str())
+                value.get("desc").and_then(|v| v.as_str()),
             ) {
-                queries.insert(q_id.to_string(), 
-                CQuery {
-                    query: query.to_string(),
-                    name: name.to_string(),
-                    desc: desc.to_string(),
-                });
+                queries.insert(
+                    q_id.to_string(),
+                    CQuery {
+                        query: query.to_string(),
+                        name: name.to_string(),
+                        desc: desc.to_string(),
+                    },
+                );
             }
         }
     }
-    else {
-    }
     Ok(queries)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_backslashes_before_quotes() {
+        assert_eq!(escape(r#"say "hi""#), r#"say \"hi\""#);
+        assert_eq!(escape("it's"), r"it\'s");
+        // otherwise the backslash would escape the added one, closing the string
+        assert_eq!(escape(r#"a\" SET x"#), r#"a\\\" SET x"#);
+        assert_eq!(escape(r"ends\"), r"ends\\");
+    }
+
+    #[test]
+    fn metadata_values_stay_inside_their_string() {
+        let queries = CQueryStorage::new();
+        let q = queries.get_set_relationship_property("li", "laugh", "gloss", r#"to "laugh"\"#);
+        assert!(q.query.contains(r#"SET r.gloss = "to \"laugh\"\\";"#));
+    }
+}
@@ -1 +1 @@
-pub mod cquery;
\ No newline at end of file
+pub mod cquery;
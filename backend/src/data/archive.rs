@@ -0,0 +1,380 @@
+use crate::data::storage::{get_timestamp, MGCollection, Settings};
+use crate::data::store::MGStore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{self, Cursor, Read, Write};
+use utoipa::ToSchema;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+// increased whenever the archive layout changes
+const ARCHIVE_VERSION: u32 = 1;
+// the upload is capped compressed, these stop a small zip from unpacking into gigabytes
+const MAX_ENTRY_SIZE: u64 = 16 * 1024 * 1024;
+const MAX_UNPACKED_SIZE: u64 = 256 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    Json, // a single JSON document
+    Zip,  // manifest.json, settings.json and mgs/<id>.json
+}
+
+/* What to do with an archived MG whose id or title is already in the collection. */
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    Skip,
+    Rename,
+    Overwrite,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveManifest {
+    version: u32,
+    exported_at: u64,
+    mg_ids: Vec<String>,
+}
+
+/* A whole collection with its history, and settings without any credentials. */
+#[derive(Serialize, Deserialize)]
+pub struct MGArchive {
+    pub version: u32,
+    pub exported_at: u64,
+    pub mgs: MGCollection,
+    pub settings: Option<Settings>,
+}
+
+#[derive(Serialize, Default, ToSchema)]
+pub struct ImportSummary {
+    pub imported: Vec<String>,
+    pub renamed: Vec<String>,
+    pub overwritten: Vec<String>,
+    pub skipped: Vec<String>,
+    pub settings_imported: bool,
+}
+
+fn to_invalid_data<E: std::fmt::Display>(e: E) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid archive: {}", e),
+    )
+}
+
+impl MGArchive {
+    pub async fn from_store(store: &dyn MGStore) -> io::Result<Self> {
+        Ok(Self {
+            version: ARCHIVE_VERSION,
+            exported_at: get_timestamp(),
+            mgs: store.load_mg_collection().await?,
+            // the password is never serialised
+            settings: store.load_settings().await.ok(),
+        })
+    }
+
+    pub fn to_bytes(&self, format: ArchiveFormat) -> io::Result<Vec<u8>> {
+        match format {
+            ArchiveFormat::Json => Ok(serde_json::to_vec_pretty(self)?),
+            ArchiveFormat::Zip => self.to_zip(),
+        }
+    }
+
+    /* Zip archives are recognised by their signature, anything else is read as JSON. */
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let archive: Self = if bytes.starts_with(b"PK\x03\x04") {
+            Self::from_zip(bytes)?
+        } else {
+            serde_json::from_slice(bytes).map_err(to_invalid_data)?
+        };
+
+        if archive.version > ARCHIVE_VERSION {
+            return Err(to_invalid_data(format!(
+                "version {} is newer than this application supports",
+                archive.version
+            )));
+        }
+        Ok(archive)
+    }
+
+    fn to_zip(&self) -> io::Result<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+
+        let manifest = ArchiveManifest {
+            version: self.version,
+            exported_at: self.exported_at,
+            mg_ids: self.mgs.iter().map(|mg| mg.id.clone()).collect(),
+        };
+        zip.start_file("manifest.json", options)?;
+        zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+
+        if let Some(settings) = &self.settings {
+            zip.start_file("settings.json", options)?;
+            zip.write_all(&serde_json::to_vec_pretty(settings)?)?;
+        }
+
+        for mg in self.mgs.iter() {
+            zip.start_file(format!("mgs/{}.json", mg.id), options)?;
+            zip.write_all(&serde_json::to_vec_pretty(mg)?)?;
+        }
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    fn from_zip(bytes: &[u8]) -> io::Result<Self> {
+        let mut zip = ZipArchive::new(Cursor::new(bytes)).map_err(to_invalid_data)?;
+        let mut unpacked: u64 = 0;
+        let mut read_file = |name: &str| -> io::Result<Option<Vec<u8>>> {
+            match zip.by_name(name) {
+                Ok(file) => {
+                    // the sizes in the zip can't be trusted, so stop reading at the limit
+                    let limit = MAX_ENTRY_SIZE.min(MAX_UNPACKED_SIZE - unpacked);
+                    let mut contents = Vec::new();
+                    file.take(limit + 1).read_to_end(&mut contents)?;
+                    if contents.len() as u64 > limit {
+                        return Err(to_invalid_data(format!("{} is too large to import", name)));
+                    }
+                    unpacked += contents.len() as u64;
+                    Ok(Some(contents))
+                }
+                Err(zip::result::ZipError::FileNotFound) => Ok(None),
+                Err(e) => Err(to_invalid_data(e)),
+            }
+        };
+
+        let manifest: ArchiveManifest = match read_file("manifest.json")? {
+            Some(contents) => serde_json::from_slice(&contents).map_err(to_invalid_data)?,
+            None => return Err(to_invalid_data("missing manifest.json")),
+        };
+        let settings: Option<Settings> = match read_file("settings.json")? {
+            Some(contents) => Some(serde_json::from_slice(&contents).map_err(to_invalid_data)?),
+            None => None,
+        };
+
+        // the manifest keeps the order of the collection
+        let mut mgs = MGCollection::new();
+        for id in manifest.mg_ids.iter() {
+            let contents = read_file(&format!("mgs/{}.json", id))?
+                .ok_or_else(|| to_invalid_data(format!("missing MG {}", id)))?;
+            mgs.push(serde_json::from_slice(&contents).map_err(to_invalid_data)?);
+        }
+
+        Ok(Self {
+            version: manifest.version,
+            exported_at: manifest.exported_at,
+            mgs,
+            settings,
+        })
+    }
+
+    /*
+    Adds the archived MGs to the store. An MG conflicts with a saved one
+    if it has the same id or title, identical MGs are always skipped.
+    */
+    pub async fn import_into(
+        self,
+        store: &dyn MGStore,
+        policy: ConflictPolicy,
+        import_settings: bool,
+    ) -> io::Result<ImportSummary> {
+        let mut summary = ImportSummary::default();
+        let mut existing = store.load_mg_collection().await?;
+        let mut titles: HashSet<String> = existing.iter().map(|mg| mg.title.clone()).collect();
+
+        for mut mg in self.mgs.into_iter() {
+            mg.ensure_id();
+            let conflict = existing
+                .iter()
+                .position(|x| x.id == mg.id)
+                .or_else(|| existing.iter().position(|x| x.title == mg.title));
+
+            let Some(i) = conflict else {
+                titles.insert(mg.title.clone());
+                summary.imported.push(mg.title.clone());
+                store.save_mg(&mg).await?;
+                existing.push(mg);
+                continue;
+            };
+
+            if existing[i].is_same_grammar(&mg) && existing[i].history.len() >= mg.history.len() {
+                summary.skipped.push(mg.title);
+                continue;
+            }
+
+            match policy {
+                ConflictPolicy::Skip => summary.skipped.push(mg.title),
+                ConflictPolicy::Overwrite => {
+                    mg.id = existing[i].id.clone();
+                    summary.overwritten.push(mg.title.clone());
+                    store.save_mg(&mg).await?;
+                    existing[i] = mg;
+                }
+                ConflictPolicy::Rename => {
+                    mg.id = uuid::Uuid::new_v4().to_string();
+                    mg.title = Self::get_free_title(&mg.title, &titles);
+                    titles.insert(mg.title.clone());
+                    summary.renamed.push(mg.title.clone());
+                    store.save_mg(&mg).await?;
+                    existing.push(mg);
+                }
+            }
+        }
+
+        if let Some(settings) = self.settings.filter(|_| import_settings) {
+            // archives have no password, so the saved one is kept
+            store.save_settings(&settings).await?;
+            summary.settings_imported = true;
+        }
+        Ok(summary)
+    }
+
+    /* e.g My MG (2) */
+    fn get_free_title(title: &str, titles: &HashSet<String>) -> String {
+        (2..)
+            .map(|n| format!("{} ({})", title, n))
+            .find(|t| !titles.contains(t))
+            .unwrap_or_else(|| title.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::sqlite::SqliteStore;
+    use crate::data::storage::MGExample;
+    use crate::test_utils::{get_temp_dir, use_test_data_dir, DATA_DIR_LOCK};
+
+    /* A saved MG with a single version. */
+    fn get_mg(id: &str, title: &str, grammar: &str) -> MGExample {
+        let mut mg = MGExample {
+            id: id.to_string(),
+            ..crate::test_utils::get_mg(title, &[])
+        };
+        mg.add_version(
+            title.to_string(),
+            mg.lang.clone(),
+            vec![grammar.to_string()],
+            None,
+        );
+        mg
+    }
+
+    fn get_archive(mgs: MGCollection) -> MGArchive {
+        MGArchive {
+            version: ARCHIVE_VERSION,
+            exported_at: 1,
+            mgs,
+            settings: Some(Settings {
+                db_addr: String::from("bolt://localhost:7687"),
+                db_name: String::from("neo4j"),
+                username: String::from("neo4j"),
+                password: String::from("secret"),
+            }),
+        }
+    }
+
+    #[test]
+    fn round_trips_without_the_password() {
+        let mgs = vec![
+            get_mg("b", "B", "Mary :: d;"),
+            get_mg("a", "A", "John :: d;"),
+        ];
+        for format in [ArchiveFormat::Json, ArchiveFormat::Zip] {
+            let bytes = get_archive(mgs.clone()).to_bytes(format).unwrap();
+            assert!(!String::from_utf8_lossy(&bytes).contains("secret"));
+
+            let archive = MGArchive::from_bytes(&bytes).unwrap();
+            let ids: Vec<&str> = archive.mgs.iter().map(|mg| mg.id.as_str()).collect();
+            assert_eq!(ids, vec!["b", "a"]);
+            assert_eq!(archive.mgs[0].history.len(), 1);
+            let settings = archive.settings.unwrap();
+            assert_eq!(settings.username, "neo4j");
+            assert!(settings.password.is_empty());
+        }
+    }
+
+    #[test]
+    fn newer_archives_are_rejected() {
+        let mut archive = get_archive(Vec::new());
+        archive.version = ARCHIVE_VERSION + 1;
+        let bytes = archive.to_bytes(ArchiveFormat::Json).unwrap();
+        let error = MGArchive::from_bytes(&bytes).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn large_entries_are_not_unpacked() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        zip.start_file("manifest.json", options).unwrap();
+        // compresses to a few kilobytes
+        zip.write_all(&vec![b' '; MAX_ENTRY_SIZE as usize + 1])
+            .unwrap();
+        let bytes = zip.finish().unwrap().into_inner();
+        assert!(bytes.len() < 1024 * 1024);
+
+        let error = MGArchive::from_bytes(&bytes).err().unwrap();
+        assert!(error.to_string().contains("too large"));
+    }
+
+    /* Imports an archive with an MG conflicting by id, one by title, one identical and one new. */
+    async fn import_with(policy: ConflictPolicy) -> (ImportSummary, MGCollection) {
+        use_test_data_dir();
+        let _guard = DATA_DIR_LOCK.lock().await;
+        let db_dir = get_temp_dir();
+        let store = SqliteStore::open(&db_dir.join(SqliteStore::DB_PATH))
+            .await
+            .unwrap();
+        for mg in [
+            get_mg("1", "A", "Mary :: d;"),
+            get_mg("2", "B", "John :: d;"),
+            get_mg("3", "C", "Sue :: d;"),
+        ] {
+            store.save_mg(&mg).await.unwrap();
+        }
+
+        let archive = get_archive(vec![
+            get_mg("1", "A", "Mary :: d -k;"),
+            get_mg("9", "B", "John :: d -k;"),
+            get_mg("3", "C", "Sue :: d;"),
+            get_mg("4", "D", "Bill :: d;"),
+        ]);
+        let summary = archive.import_into(&store, policy, false).await.unwrap();
+        let mgs = store.load_mg_collection().await.unwrap();
+        drop(store);
+        std::fs::remove_dir_all(db_dir).unwrap();
+        (summary, mgs)
+    }
+
+    fn get_titles(mgs: &MGCollection) -> Vec<&str> {
+        mgs.iter().map(|mg| mg.title.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn conflicts_are_skipped() {
+        let (summary, mgs) = import_with(ConflictPolicy::Skip).await;
+        assert_eq!(summary.imported, vec!["D"]);
+        assert_eq!(summary.skipped, vec!["A", "B", "C"]);
+        assert_eq!(mgs[0].grammar, vec!["Mary :: d;"]);
+        assert!(!summary.settings_imported);
+    }
+
+    #[tokio::test]
+    async fn conflicts_are_renamed() {
+        let (summary, mgs) = import_with(ConflictPolicy::Rename).await;
+        assert_eq!(summary.renamed, vec!["A (2)", "B (2)"]);
+        // identical MGs are never imported twice
+        assert_eq!(summary.skipped, vec!["C"]);
+        assert_eq!(get_titles(&mgs), vec!["A", "B", "C", "A (2)", "B (2)", "D"]);
+    }
+
+    #[tokio::test]
+    async fn conflicts_are_overwritten() {
+        let (summary, mgs) = import_with(ConflictPolicy::Overwrite).await;
+        assert_eq!(summary.overwritten, vec!["A", "B"]);
+        assert_eq!(get_titles(&mgs), vec!["A", "B", "C", "D"]);
+        // the saved id is kept when matched by title
+        assert_eq!(mgs[1].id, "2");
+        assert_eq!(mgs[1].grammar, vec!["John :: d -k;"]);
+    }
+}
@@ -1 +1,6 @@
-pub mod storage;
\ No newline at end of file
+pub mod archive;
+pub mod search;
+pub mod secrets;
+pub mod sqlite;
+pub mod storage;
+pub mod store;
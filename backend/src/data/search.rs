@@ -0,0 +1,319 @@
+use crate::calculator;
+use crate::data::storage::{MGCollection, MGExample};
+use crate::parse::mg::{is_empty_morph, LIRelation, EPSILON};
+use crate::parse::parser::Parser;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    Title,
+    Lang,
+    Mdl,
+    Saved, // when the current version was saved
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/* Every filter is optional, an empty query returns the whole collection. */
+#[derive(Deserialize, Default, Debug, ToSchema, IntoParams)]
+pub struct MGQuery {
+    pub lang: Option<String>,     // exact, ignoring case
+    pub title: Option<String>,    // substring, ignoring case
+    pub morph: Option<String>,    // an item with this morph, ε for silent heads
+    pub feature: Option<String>,  // an item with this feature as written e.g +wh
+    pub category: Option<String>, // an item with this category
+    pub min_mdl: Option<f64>,
+    pub max_mdl: Option<f64>,
+    pub sort: Option<SortKey>,
+    #[serde(default)]
+    pub order: SortOrder,
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+pub struct SearchResult {
+    // the number of matches before pagination
+    pub total: usize,
+    pub mgs: MGCollection,
+}
+
+/* The MDL recorded when the current version was saved, calculated for older MGs. */
+fn get_mdl(mg: &MGExample) -> Option<f64> {
+    if let Some(mdl) = mg.history.last().and_then(|v| v.mdl) {
+        return Some(mdl);
+    }
+    calculator::get_saved_mdl(&mg.grammar)
+}
+
+fn get_saved_at(mg: &MGExample) -> u64 {
+    mg.history.last().map(|v| v.saved_at).unwrap_or(0)
+}
+
+impl MGQuery {
+    pub fn is_match(&self, mg: &MGExample) -> bool {
+        if self
+            .lang
+            .as_ref()
+            .is_some_and(|lang| !mg.lang.eq_ignore_ascii_case(lang.trim()))
+        {
+            return false;
+        }
+        if self
+            .title
+            .as_ref()
+            .is_some_and(|title| !mg.title.to_lowercase().contains(&title.to_lowercase()))
+        {
+            return false;
+        }
+        if (self.min_mdl.is_some() || self.max_mdl.is_some()) && !self.is_mdl_match(get_mdl(mg)) {
+            return false;
+        }
+        if self.morph.is_none() && self.feature.is_none() && self.category.is_none() {
+            return true;
+        }
+
+        let statements = Parser::get_statements(&mg.grammar.join("\n"));
+        let has_morph = |morph: &str| {
+            statements.iter().any(|s| {
+                let m = if is_empty_morph(&s.morph) {
+                    EPSILON
+                } else {
+                    s.morph.as_str()
+                };
+                m == morph || (is_empty_morph(morph) && m == EPSILON)
+            })
+        };
+        let has_feature = |feature: &str| {
+            statements
+                .iter()
+                .flat_map(|s| s.features.split_whitespace())
+                .any(|f| f == feature)
+        };
+        let has_category = |category: &str| {
+            statements
+                .iter()
+                .flat_map(|s| s.features.split_whitespace())
+                .map(|f| LIRelation::from_raw(f, true))
+                .any(|(rel, id)| matches!(rel, LIRelation::State) && id == category)
+        };
+
+        self.morph.as_deref().is_none_or(has_morph)
+            && self.feature.as_deref().is_none_or(has_feature)
+            && self.category.as_deref().is_none_or(has_category)
+    }
+
+    fn is_mdl_match(&self, mdl: Option<f64>) -> bool {
+        let Some(mdl) = mdl else {
+            return false;
+        };
+        self.min_mdl.is_none_or(|min| mdl >= min) && self.max_mdl.is_none_or(|max| mdl <= max)
+    }
+
+    pub fn search(&self, mgs: MGCollection) -> SearchResult {
+        let mut matches: MGCollection = mgs.into_iter().filter(|mg| self.is_match(mg)).collect();
+
+        if let Some(sort) = self.sort {
+            // MDLs are worked out once rather than on every comparison
+            let mut keyed: Vec<(Option<f64>, MGExample)> = matches
+                .into_iter()
+                .map(|mg| {
+                    (
+                        if sort == SortKey::Mdl {
+                            get_mdl(&mg)
+                        } else {
+                            None
+                        },
+                        mg,
+                    )
+                })
+                .collect();
+            let compare = |(mdl_a, a): &(Option<f64>, MGExample),
+                           (mdl_b, b): &(Option<f64>, MGExample)| {
+                match sort {
+                    SortKey::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+                    SortKey::Lang => a.lang.to_lowercase().cmp(&b.lang.to_lowercase()),
+                    SortKey::Saved => get_saved_at(a).cmp(&get_saved_at(b)),
+                    SortKey::Mdl => match (mdl_a, mdl_b) {
+                        (Some(x), Some(y)) => x.partial_cmp(y).unwrap_or(Ordering::Equal),
+                        (Some(_), None) => Ordering::Less,
+                        (None, Some(_)) => Ordering::Greater,
+                        (None, None) => Ordering::Equal,
+                    },
+                }
+            };
+            match self.order {
+                SortOrder::Asc => keyed.sort_by(compare),
+                SortOrder::Desc => keyed.sort_by(|a, b| compare(b, a)),
+            }
+            matches = keyed.into_iter().map(|(_, mg)| mg).collect();
+        }
+
+        let total = matches.len();
+        let mgs = matches
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect();
+        SearchResult { total, mgs }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::storage::MGVersion;
+    use crate::test_utils::lines;
+
+    /* A saved MG whose only version was saved at the given time. */
+    fn get_mg(
+        title: &str,
+        lang: &str,
+        grammar: &[&str],
+        mdl: Option<f64>,
+        saved_at: u64,
+    ) -> MGExample {
+        MGExample {
+            id: title.to_string(),
+            lang: lang.to_string(),
+            history: vec![MGVersion {
+                version: 1,
+                saved_at,
+                title: title.to_string(),
+                lang: lang.to_string(),
+                grammar: lines(grammar),
+                mdl,
+            }],
+            ..crate::test_utils::get_mg(title, grammar)
+        }
+    }
+
+    fn get_collection() -> MGCollection {
+        vec![
+            get_mg(
+                "English wh",
+                "en",
+                &["who :: d -wh;", "ε :: =t +wh c;"],
+                Some(30.0),
+                3,
+            ),
+            get_mg(
+                "German",
+                "DE",
+                &["Maria :: d -k;", "lacht :: =d +k v;"],
+                Some(10.0),
+                1,
+            ),
+            get_mg("English", "en", &["Mary :: d;", "laughs :: =d v;"], None, 2),
+        ]
+    }
+
+    fn search(query: MGQuery) -> Vec<String> {
+        query
+            .search(get_collection())
+            .mgs
+            .into_iter()
+            .map(|mg| mg.title)
+            .collect()
+    }
+
+    #[test]
+    fn filters_by_lang_and_title_ignoring_case() {
+        let query = MGQuery {
+            lang: Some(String::from("de")),
+            ..Default::default()
+        };
+        assert_eq!(search(query), vec!["German"]);
+        let query = MGQuery {
+            title: Some(String::from("WH")),
+            ..Default::default()
+        };
+        assert_eq!(search(query), vec!["English wh"]);
+    }
+
+    #[test]
+    fn filters_by_lexical_items() {
+        let query = MGQuery {
+            morph: Some(String::from("[]")),
+            ..Default::default()
+        };
+        assert_eq!(search(query), vec!["English wh"]);
+        let query = MGQuery {
+            feature: Some(String::from("+k")),
+            ..Default::default()
+        };
+        assert_eq!(search(query), vec!["German"]);
+        let query = MGQuery {
+            category: Some(String::from("d")),
+            ..Default::default()
+        };
+        assert_eq!(search(query).len(), 3);
+        // a selector isn't a category
+        let query = MGQuery {
+            category: Some(String::from("t")),
+            ..Default::default()
+        };
+        assert!(search(query).is_empty());
+    }
+
+    #[test]
+    fn filters_by_mdl_calculating_missing_ones() {
+        let query = MGQuery {
+            max_mdl: Some(20.0),
+            ..Default::default()
+        };
+        assert_eq!(search(query), vec!["German"]);
+        // English has no recorded MDL
+        let query = MGQuery {
+            min_mdl: Some(20.0),
+            max_mdl: Some(1000.0),
+            ..Default::default()
+        };
+        assert_eq!(search(query), vec!["English wh", "English"]);
+    }
+
+    #[test]
+    fn sorts_and_pages() {
+        let query = MGQuery {
+            sort: Some(SortKey::Saved),
+            order: SortOrder::Desc,
+            ..Default::default()
+        };
+        assert_eq!(search(query), vec!["English wh", "English", "German"]);
+
+        let query = MGQuery {
+            sort: Some(SortKey::Title),
+            offset: 1,
+            limit: Some(1),
+            ..Default::default()
+        };
+        let result = query.search(get_collection());
+        assert_eq!(result.total, 3);
+        assert_eq!(result.mgs.len(), 1);
+        assert_eq!(result.mgs[0].title, "English wh");
+    }
+
+    #[test]
+    fn sorting_by_mdl() {
+        let query = MGQuery {
+            sort: Some(SortKey::Mdl),
+            ..Default::default()
+        };
+        assert_eq!(search(query)[0], "German");
+        let query = MGQuery {
+            sort: Some(SortKey::Mdl),
+            order: SortOrder::Desc,
+            ..Default::default()
+        };
+        assert_eq!(search(query).last().unwrap(), "German");
+    }
+}
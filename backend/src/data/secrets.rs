@@ -0,0 +1,141 @@
+use crate::data::storage::DataManager;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const NEO4J_PASSWORD: &str = "neo4j_password";
+
+const NONCE_SIZE: usize = 12;
+
+/*
+Credentials are kept out of the settings in a file encrypted with
+ChaCha20-Poly1305, using a master key generated on first use and
+readable only by the current user.
+*/
+pub struct Secrets;
+
+impl Secrets {
+    const MASTER_KEY_PATH: &str = "master.key";
+    const SECRETS_PATH: &str = "credentials.enc";
+
+    pub fn get(name: &str) -> io::Result<Option<String>> {
+        Ok(Self::load()?.remove(name))
+    }
+
+    pub fn set(name: &str, value: &str) -> io::Result<()> {
+        let mut secrets = Self::load()?;
+        secrets.insert(name.to_string(), value.to_string());
+        Self::save(&secrets)
+    }
+
+    fn load() -> io::Result<BTreeMap<String, String>> {
+        let path = DataManager::get_config_path(Self::SECRETS_PATH);
+        if !path.exists() {
+            return Ok(BTreeMap::new());
+        }
+
+        let contents = fs::read(path)?;
+        if contents.len() < NONCE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Credentials file is corrupt.",
+            ));
+        }
+        let (nonce, ciphertext) = contents.split_at(NONCE_SIZE);
+        let plaintext = Self::get_cipher()?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Credentials could not be decrypted with the master key.",
+                )
+            })?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+
+    fn save(secrets: &BTreeMap<String, String>) -> io::Result<()> {
+        let plaintext = serde_json::to_vec(secrets)?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = Self::get_cipher()?
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| io::Error::other("Credentials could not be encrypted."))?;
+
+        let mut contents = nonce.to_vec();
+        contents.extend(ciphertext);
+        Self::write_private(&DataManager::get_config_path(Self::SECRETS_PATH), &contents)
+    }
+
+    fn get_cipher() -> io::Result<ChaCha20Poly1305> {
+        let path = DataManager::get_config_path(Self::MASTER_KEY_PATH);
+        if !path.exists() {
+            let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+            Self::write_private(&path, key.as_slice())?;
+        }
+
+        let key = fs::read(path)?;
+        if key.len() != 32 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Master key is corrupt.",
+            ));
+        }
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+
+    /* Writes a file which only the current user can read. */
+    fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        io::Write::write_all(&mut options.open(path)?, contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{use_test_data_dir, DATA_DIR_LOCK};
+
+    #[tokio::test]
+    async fn secrets_are_encrypted_at_rest() {
+        let dir = use_test_data_dir();
+        let _guard = DATA_DIR_LOCK.lock().await;
+
+        assert_eq!(Secrets::get(NEO4J_PASSWORD).unwrap(), None);
+        Secrets::set(NEO4J_PASSWORD, "hunter2").unwrap();
+        Secrets::set("other", "value").unwrap();
+        assert_eq!(
+            Secrets::get(NEO4J_PASSWORD).unwrap().as_deref(),
+            Some("hunter2")
+        );
+
+        let contents = fs::read(dir.join(Secrets::SECRETS_PATH)).unwrap();
+        assert!(!String::from_utf8_lossy(&contents).contains("hunter2"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for file in [Secrets::SECRETS_PATH, Secrets::MASTER_KEY_PATH] {
+                let mode = fs::metadata(dir.join(file)).unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, 0o600);
+            }
+        }
+
+        // another key can't read them
+        fs::remove_file(dir.join(Secrets::MASTER_KEY_PATH)).unwrap();
+        let error = Secrets::get(NEO4J_PASSWORD).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(dir.join(Secrets::SECRETS_PATH)).unwrap();
+        fs::remove_file(dir.join(Secrets::MASTER_KEY_PATH)).unwrap();
+    }
+}
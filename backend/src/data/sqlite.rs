@@ -0,0 +1,483 @@
+use crate::data::storage::{
+    get_timestamp, DataManager, MGCollection, MGExample, MGVersion, Settings,
+};
+use crate::data::store::{MGStore, MAX_CACHED};
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde_json::{Map, Value};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS mgs (
+        id TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        lang TEXT NOT NULL,
+        grammar TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS versions (
+        mg_id TEXT NOT NULL REFERENCES mgs(id) ON DELETE CASCADE,
+        version INTEGER NOT NULL,
+        saved_at INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        lang TEXT NOT NULL,
+        grammar TEXT NOT NULL,
+        mdl REAL,
+        PRIMARY KEY (mg_id, version)
+    );
+    CREATE TABLE IF NOT EXISTS settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS analysis_cache (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+";
+
+// set once mg.json and settings.json have been copied into the database
+const JSON_MIGRATED_KEY: &str = "json_migrated";
+
+fn to_io_error<E: std::error::Error + Send + Sync + 'static>(e: E) -> io::Error {
+    io::Error::other(e)
+}
+
+/*
+An embedded SQLite database holding everything the JSON backend stored.
+Each operation runs in its own transaction on a blocking thread.
+*/
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    pub const DB_PATH: &str = "mggraph.db";
+
+    pub async fn open(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let conn = Connection::open(path).map_err(to_io_error)?;
+        conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")
+            .map_err(to_io_error)?;
+        conn.execute_batch(SCHEMA).map_err(to_io_error)?;
+
+        let store = Self {
+            conn: Arc::new(Mutex::new(conn)),
+        };
+        store.migrate_from_json().await?;
+        Ok(store)
+    }
+
+    /* Runs a database operation without blocking the async workers. */
+    async fn run<T, F>(&self, op: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = Arc::clone(&self.conn);
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| io::Error::other("SQLite connection poisoned"))?;
+            op(&mut conn).map_err(to_io_error)
+        })
+        .await
+        .map_err(to_io_error)?
+    }
+
+    /* Copies the old JSON files into the database the first time it is opened. */
+    async fn migrate_from_json(&self) -> io::Result<()> {
+        let is_migrated = self
+            .run(|conn| {
+                conn.query_row(
+                    "SELECT value FROM meta WHERE key = ?1",
+                    [JSON_MIGRATED_KEY],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()
+            })
+            .await?;
+        if is_migrated.is_some() {
+            return Ok(());
+        }
+
+        let mut mgs: MGCollection = DataManager::load_mg_collection::<MGCollection>()
+            .await
+            .unwrap_or_default();
+        mgs.iter_mut().for_each(|mg| mg.ensure_id());
+        // read as is so that a plain text password can still be moved to the secret store
+        let settings: Option<Value> = DataManager::load_settings::<Value>().await.ok();
+        tracing::info!(count = mgs.len(), "Migrating saved MGs from JSON to SQLite");
+
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+            for (position, mg) in mgs.iter().enumerate() {
+                write_mg(&tx, mg, Some(position as i64))?;
+            }
+            if let Some(settings) = settings {
+                write_settings(&tx, settings)?;
+            }
+            tx.execute(
+                "INSERT INTO meta (key, value) VALUES (?1, ?2)",
+                params![JSON_MIGRATED_KEY, get_timestamp().to_string()],
+            )?;
+            tx.commit()
+        })
+        .await
+    }
+}
+
+fn write_mg(conn: &Connection, mg: &MGExample, position: Option<i64>) -> rusqlite::Result<()> {
+    let grammar = serde_json::to_string(&mg.grammar)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    // new MGs go to the end of the collection, existing ones keep their place
+    let position: i64 = match position {
+        Some(p) => p,
+        None => conn.query_row(
+            "SELECT COALESCE((SELECT position FROM mgs WHERE id = ?1), (SELECT COALESCE(MAX(position) + 1, 0) FROM mgs))",
+            [&mg.id], |row| row.get(0))?,
+    };
+
+    conn.execute(
+        "INSERT INTO mgs (id, position, title, lang, grammar) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(id) DO UPDATE SET position = ?2, title = ?3, lang = ?4, grammar = ?5",
+        params![mg.id, position, mg.title, mg.lang, grammar],
+    )?;
+
+    conn.execute("DELETE FROM versions WHERE mg_id = ?1", [&mg.id])?;
+    for v in mg.history.iter() {
+        let grammar = serde_json::to_string(&v.grammar)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        conn.execute(
+            "INSERT INTO versions (mg_id, version, saved_at, title, lang, grammar, mdl) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![mg.id, v.version as i64, v.saved_at as i64, v.title, v.lang, grammar, v.mdl])?;
+    }
+    Ok(())
+}
+
+fn to_json(grammar: &[String]) -> rusqlite::Result<String> {
+    serde_json::to_string(grammar).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+/*
+Adds one row to the history of an MG and makes it the current content,
+or returns None if there is no MG with the id. Must be run in a transaction.
+*/
+fn append_version(
+    conn: &Connection,
+    id: &str,
+    title: &str,
+    lang: &str,
+    grammar: &[String],
+    mdl: Option<f64>,
+) -> rusqlite::Result<Option<usize>> {
+    let current: Option<(String, String, String)> = conn
+        .query_row(
+            "SELECT title, lang, grammar FROM mgs WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    let Some((old_title, old_lang, old_grammar)) = current else {
+        return Ok(None);
+    };
+    let last: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM versions WHERE mg_id = ?1",
+        [id],
+        |row| row.get(0),
+    )?;
+
+    // MGs saved before versioning keep their original content as version 1
+    let mut last = last;
+    if last == 0 && !read_grammar(old_grammar.clone()).is_empty() {
+        conn.execute(
+            "INSERT INTO versions (mg_id, version, saved_at, title, lang, grammar, mdl) VALUES (?1, 1, 0, ?2, ?3, ?4, NULL)",
+            params![id, old_title, old_lang, old_grammar],
+        )?;
+        last = 1;
+    }
+
+    let version = last + 1;
+    let grammar = to_json(grammar)?;
+    conn.execute(
+        "INSERT INTO versions (mg_id, version, saved_at, title, lang, grammar, mdl) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![id, version, get_timestamp() as i64, title, lang, grammar, mdl],
+    )?;
+    conn.execute(
+        "UPDATE mgs SET title = ?2, lang = ?3, grammar = ?4 WHERE id = ?1",
+        params![id, title, lang, grammar],
+    )?;
+    Ok(Some(version as usize))
+}
+
+fn read_grammar(json: String) -> Vec<String> {
+    serde_json::from_str(&json).unwrap_or_default()
+}
+
+fn read_mgs(conn: &Connection, id: Option<&str>) -> rusqlite::Result<MGCollection> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, lang, grammar FROM mgs WHERE ?1 IS NULL OR id = ?1 ORDER BY position",
+    )?;
+    let mut mgs: MGCollection = stmt
+        .query_map([id], |row| {
+            Ok(MGExample {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                lang: row.get(2)?,
+                grammar: read_grammar(row.get(3)?),
+                history: Vec::new(),
+            })
+        })?
+        .collect::<rusqlite::Result<MGCollection>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT version, saved_at, title, lang, grammar, mdl FROM versions WHERE mg_id = ?1 ORDER BY version")?;
+    for mg in mgs.iter_mut() {
+        mg.history = stmt
+            .query_map([&mg.id], |row| {
+                Ok(MGVersion {
+                    version: row.get::<_, i64>(0)? as usize,
+                    saved_at: row.get::<_, i64>(1)? as u64,
+                    title: row.get(2)?,
+                    lang: row.get(3)?,
+                    grammar: read_grammar(row.get(4)?),
+                    mdl: row.get(5)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<MGVersion>>>()?;
+    }
+    Ok(mgs)
+}
+
+/* Settings are stored one field per row, replacing any previous settings. */
+fn write_settings(conn: &Connection, settings: Value) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM settings", [])?;
+    if let Value::Object(fields) = settings {
+        for (key, value) in fields {
+            conn.execute(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = ?2",
+                params![key, value.to_string()])?;
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl MGStore for SqliteStore {
+    async fn load_mg_collection(&self) -> io::Result<MGCollection> {
+        self.run(|conn| read_mgs(conn, None)).await
+    }
+
+    async fn get_mg(&self, id: &str) -> io::Result<Option<MGExample>> {
+        let id = id.to_string();
+        self.run(move |conn| Ok(read_mgs(conn, Some(&id))?.pop()))
+            .await
+    }
+
+    async fn save_mg(&self, mg: &MGExample) -> io::Result<()> {
+        let mg = mg.clone();
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+            write_mg(&tx, &mg, None)?;
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn insert_if_absent(&self, mg: &MGExample) -> io::Result<(String, bool)> {
+        let mg = mg.clone();
+        self.run(move |conn| {
+            // immediate, so another process can't insert between the check and the write
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let existing: Option<String> = tx
+                .query_row(
+                    "SELECT id FROM mgs WHERE title = ?1 AND lang = ?2 AND grammar = ?3 ORDER BY position LIMIT 1",
+                    params![mg.title, mg.lang, to_json(&mg.grammar)?],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(id) = existing {
+                return Ok((id, false));
+            }
+            write_mg(&tx, &mg, None)?;
+            tx.commit()?;
+            Ok((mg.id, true))
+        })
+        .await
+    }
+
+    async fn add_version(
+        &self,
+        id: &str,
+        title: String,
+        lang: String,
+        grammar: Vec<String>,
+        mdl: Option<f64>,
+    ) -> io::Result<Option<usize>> {
+        let id = id.to_string();
+        self.run(move |conn| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let version = append_version(&tx, &id, &title, &lang, &grammar, mdl)?;
+            tx.commit()?;
+            Ok(version)
+        })
+        .await
+    }
+
+    async fn restore_version(&self, id: &str, version: usize) -> io::Result<Option<usize>> {
+        let id = id.to_string();
+        self.run(move |conn| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let old: Option<(String, String, String, Option<f64>)> = tx
+                .query_row(
+                    "SELECT title, lang, grammar, mdl FROM versions WHERE mg_id = ?1 AND version = ?2",
+                    params![id, version as i64],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+                )
+                .optional()?;
+            let Some((title, lang, grammar, mdl)) = old else {
+                return Ok(None);
+            };
+            let version = append_version(&tx, &id, &title, &lang, &read_grammar(grammar), mdl)?;
+            tx.commit()?;
+            Ok(version)
+        })
+        .await
+    }
+
+    async fn delete_mg(&self, id: &str) -> io::Result<bool> {
+        let id = id.to_string();
+        self.run(move |conn| Ok(conn.execute("DELETE FROM mgs WHERE id = ?1", [&id])? > 0))
+            .await
+    }
+
+    async fn load_settings(&self) -> io::Result<Settings> {
+        let fields = self
+            .run(|conn| {
+                let mut stmt = conn.prepare("SELECT key, value FROM settings")?;
+                let rows = stmt.query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?;
+                rows.collect::<rusqlite::Result<Vec<(String, String)>>>()
+            })
+            .await?;
+
+        if fields.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No settings have been saved.",
+            ));
+        }
+        let settings: Map<String, Value> = fields
+            .into_iter()
+            .map(|(key, value)| (key, serde_json::from_str(&value).unwrap_or(Value::Null)))
+            .collect();
+        Ok(serde_json::from_value(Value::Object(settings))?)
+    }
+
+    async fn save_settings(&self, settings: &Settings) -> io::Result<()> {
+        let settings = serde_json::to_value(settings)?;
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+            write_settings(&tx, settings)?;
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn get_cached(&self, key: &str) -> io::Result<Option<Value>> {
+        let key = key.to_string();
+        let cached = self
+            .run(move |conn| {
+                conn.query_row(
+                    "SELECT value FROM analysis_cache WHERE key = ?1",
+                    [&key],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()
+            })
+            .await?;
+        Ok(cached.and_then(|value| serde_json::from_str(&value).ok()))
+    }
+
+    async fn set_cached(&self, key: &str, value: &Value) -> io::Result<()> {
+        let (key, value) = (key.to_string(), value.to_string());
+        self.run(move |conn| {
+            conn.execute(
+                "INSERT INTO analysis_cache (key, value, created_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(key) DO UPDATE SET value = ?2, created_at = ?3",
+                params![key, value, get_timestamp() as i64],
+            )?;
+            conn.execute(
+                "DELETE FROM analysis_cache WHERE key NOT IN
+                 (SELECT key FROM analysis_cache ORDER BY created_at DESC, rowid DESC LIMIT ?1)",
+                [MAX_CACHED as i64],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{check_store, get_mg, get_temp_dir, use_test_data_dir, DATA_DIR_LOCK};
+
+    #[tokio::test]
+    async fn sqlite_store() {
+        use_test_data_dir();
+        // opening reads the JSON files in the data directory
+        let _guard = DATA_DIR_LOCK.lock().await;
+        let db_dir = get_temp_dir();
+        let store = SqliteStore::open(&db_dir.join(SqliteStore::DB_PATH))
+            .await
+            .unwrap();
+
+        check_store(&store).await;
+
+        let cached: i64 = store
+            .run(|conn| conn.query_row("SELECT COUNT(*) FROM analysis_cache", [], |row| row.get(0)))
+            .await
+            .unwrap();
+        assert_eq!(cached, MAX_CACHED as i64);
+        drop(store);
+        std::fs::remove_dir_all(db_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn json_collection_is_migrated_once() {
+        let dir = use_test_data_dir();
+        let _guard = DATA_DIR_LOCK.lock().await;
+        let mg = get_mg("English", &["Mary :: d;"]);
+        DataManager::save_mg_collection(&vec![mg]).await.unwrap();
+
+        let db_dir = get_temp_dir();
+        let path = db_dir.join(SqliteStore::DB_PATH);
+        let store = SqliteStore::open(&path).await.unwrap();
+        let mgs = store.load_mg_collection().await.unwrap();
+        assert_eq!(mgs.len(), 1);
+        assert_eq!(mgs[0].title, "English");
+        // given an id on the way
+        assert!(!mgs[0].id.is_empty());
+
+        // deleted MGs don't come back when the database is opened again
+        assert!(store.delete_mg(&mgs[0].id).await.unwrap());
+        drop(store);
+        let store = SqliteStore::open(&path).await.unwrap();
+        assert!(store.load_mg_collection().await.unwrap().is_empty());
+
+        drop(store);
+        std::fs::remove_file(dir.join("mg.json")).unwrap();
+        std::fs::remove_dir_all(db_dir).unwrap();
+    }
+}
@@ -1,43 +1,291 @@
+use crate::data::sqlite::SqliteStore;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::fs;
 use tokio::fs::OpenOptions;
 use tokio::io::AsyncWriteExt;
-use serde::de::DeserializeOwned;
-use serde::{Serialize, Deserialize};
-use serde_json::{Value, json};
-use std::path::{PathBuf};
+use utoipa::ToSchema;
+
+// overrides the default data directory, as does the --data-dir flag
+pub const DATA_DIR_ENV: &str = "MGGRAPH_DATA_DIR";
+const APP_DIR_NAME: &str = "mggraph";
+
+// example grammars written to the collection the first time the app is run
+const EXAMPLE_MGS: &str = include_str!("examples.json");
+
+struct AppDirs {
+    data: PathBuf,
+    config: PathBuf,
+}
+
+static APP_DIRS: OnceLock<AppDirs> = OnceLock::new();
+
+/* A snapshot of a saved MG. */
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct MGVersion {
+    pub version: usize,
+    // seconds since the unix epoch
+    pub saved_at: u64,
+    pub title: String,
+    pub lang: String,
+    pub grammar: Vec<String>,
+    pub mdl: Option<f64>,
+}
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
 pub struct MGExample {
+    // MGs saved before ids existed are given one when the collection is loaded
+    #[serde(default)]
+    pub id: String,
     pub title: String,
     pub lang: String,
-    pub grammar: Vec<String>
+    pub grammar: Vec<String>,
+    // every saved version, the last being the current one
+    #[serde(default)]
+    pub history: Vec<MGVersion>,
 }
 
 pub type MGCollection = Vec<MGExample>;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, ToSchema)]
+pub struct MGDiff {
+    pub from: usize,
+    pub to: usize,
+    pub title_changed: bool,
+    pub lang_changed: bool,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub mdl_change: Option<f64>,
+}
+
+pub fn get_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl MGExample {
+    pub fn is_same_grammar(&self, other: &MGExample) -> bool {
+        self.title == other.title && self.lang == other.lang && self.grammar == other.grammar
+    }
+
+    pub fn ensure_id(&mut self) {
+        if self.id.is_empty() {
+            self.id = uuid::Uuid::new_v4().to_string();
+        }
+    }
+
+    /* Makes the given content the current version and records it in the history. */
+    pub fn add_version(
+        &mut self,
+        title: String,
+        lang: String,
+        grammar: Vec<String>,
+        mdl: Option<f64>,
+    ) -> usize {
+        // MGs saved before versioning keep their original content as version 1
+        if self.history.is_empty() && !self.grammar.is_empty() {
+            self.history.push(MGVersion {
+                version: 1,
+                saved_at: 0,
+                title: self.title.clone(),
+                lang: self.lang.clone(),
+                grammar: self.grammar.clone(),
+                mdl: None,
+            });
+        }
+
+        let version = self.history.len() + 1;
+        self.history.push(MGVersion {
+            version,
+            saved_at: get_timestamp(),
+            title: title.clone(),
+            lang: lang.clone(),
+            grammar: grammar.clone(),
+            mdl,
+        });
+
+        self.title = title;
+        self.lang = lang;
+        self.grammar = grammar;
+        version
+    }
+
+    pub fn get_version(&self, version: usize) -> Option<&MGVersion> {
+        self.history.iter().find(|v| v.version == version)
+    }
+
+    /* Lexical items added and removed between two versions. */
+    pub fn diff(&self, from: usize, to: usize) -> Option<MGDiff> {
+        let a = self.get_version(from)?;
+        let b = self.get_version(to)?;
+        let trimmed = |lines: &Vec<String>| -> Vec<String> {
+            lines
+                .iter()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty())
+                .collect()
+        };
+        let (a_lines, b_lines) = (trimmed(&a.grammar), trimmed(&b.grammar));
+
+        Some(MGDiff {
+            from,
+            to,
+            title_changed: a.title != b.title,
+            lang_changed: a.lang != b.lang,
+            added: b_lines
+                .iter()
+                .filter(|l| !a_lines.contains(l))
+                .cloned()
+                .collect(),
+            removed: a_lines
+                .iter()
+                .filter(|l| !b_lines.contains(l))
+                .cloned()
+                .collect(),
+            mdl_change: a.mdl.zip(b.mdl).map(|(x, y)| y - x),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct Settings {
     pub db_addr: String,
     pub db_name: String,
     pub username: String,
+    // kept encrypted by Secrets, only read from older plain text settings
+    #[serde(default, skip_serializing)]
     pub password: String,
 }
 
+impl fmt::Debug for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Settings")
+            .field("db_addr", &self.db_addr)
+            .field("db_name", &self.db_name)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
 pub struct DataManager;
 
 impl DataManager {
-
     const SETTINGS_PATH: &str = "settings.json";
     const MG_COLLECTION_PATH: &str = "mg.json";
+    // marks a data directory which has already been migrated or seeded
+    const INITIALISED_PATH: &str = ".initialised";
+
+    /*
+    Uses the given directory for both data and settings.
+    Must be called before any data is read, returns false if it was too late.
+    */
+    pub fn set_data_dir(dir: PathBuf) -> bool {
+        APP_DIRS
+            .set(AppDirs {
+                data: dir.clone(),
+                config: dir,
+            })
+            .is_ok()
+    }
+
+    /* $MGGRAPH_DATA_DIR if set, otherwise the XDG data and config directories e.g ~/.local/share/mggraph */
+    fn get_app_dirs() -> &'static AppDirs {
+        APP_DIRS.get_or_init(|| {
+            if let Some(dir) = std::env::var_os(DATA_DIR_ENV).filter(|d| !d.is_empty()) {
+                let dir = PathBuf::from(dir);
+                return AppDirs {
+                    data: dir.clone(),
+                    config: dir,
+                };
+            }
+            let fallback = || PathBuf::from(".").join(APP_DIR_NAME);
+            AppDirs {
+                data: dirs::data_dir()
+                    .map(|d| d.join(APP_DIR_NAME))
+                    .unwrap_or_else(fallback),
+                config: dirs::config_dir()
+                    .map(|d| d.join(APP_DIR_NAME))
+                    .unwrap_or_else(fallback),
+            }
+        })
+    }
+
+    pub fn get_data_dir() -> &'static Path {
+        &Self::get_app_dirs().data
+    }
 
     pub fn get_data_path(filename: &str) -> PathBuf {
+        Self::get_data_dir().join(filename)
+    }
+
+    pub fn get_config_path(filename: &str) -> PathBuf {
+        Self::get_app_dirs().config.join(filename)
+    }
+
+    /* Settings live in the config directory, everything else in the data directory. */
+    fn get_file_path(filename: &str) -> PathBuf {
+        if filename == Self::SETTINGS_PATH {
+            Self::get_config_path(filename)
+        } else {
+            Self::get_data_path(filename)
+        }
+    }
+
+    /* Where data was kept inside the source tree by earlier versions. */
+    fn get_legacy_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src")
+            .join("data")
+            .join("json")
+    }
 
-        // Get the directory of the running executable
-        let exe_dir: PathBuf = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("src"); 
+    /*
+    Creates the data directory on first run, copying across any data
+    from the old location or otherwise seeding it with example grammars.
+    */
+    pub async fn init_data_dir() -> std::io::Result<()> {
+        Self::init_data_dir_from(&Self::get_legacy_dir()).await
+    }
+
+    async fn init_data_dir_from(legacy_dir: &Path) -> std::io::Result<()> {
+        let dirs = Self::get_app_dirs();
+        fs::create_dir_all(&dirs.data).await?;
+        fs::create_dir_all(&dirs.config).await?;
+
+        let marker = Self::get_data_path(Self::INITIALISED_PATH);
+        if marker.exists() {
+            return Ok(());
+        }
+
+        if legacy_dir.is_dir() && legacy_dir != dirs.data {
+            let mut entries = fs::read_dir(legacy_dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let filename = entry.file_name().to_string_lossy().to_string();
+                let target = Self::get_file_path(&filename);
+                if entry.file_type().await?.is_file() && !target.exists() {
+                    tracing::info!(file = filename, to = %target.display(), "Migrating data file");
+                    fs::copy(entry.path(), target).await?;
+                }
+            }
+        }
+
+        let has_collection = Self::get_data_path(Self::MG_COLLECTION_PATH).exists()
+            || Self::get_data_path(SqliteStore::DB_PATH).exists();
+        if !has_collection {
+            let mut examples: MGCollection = serde_json::from_str(EXAMPLE_MGS)?;
+            examples.iter_mut().for_each(|mg| mg.ensure_id());
+            Self::save_mg_collection(&examples).await?;
+        }
 
-        // Construct the data path relative to exe_dir
-        exe_dir.join("data").join("json").join(filename)
+        fs::write(marker, get_timestamp().to_string()).await
     }
 
     pub async fn ensure_file_exists(path: &PathBuf) -> std::io::Result<()> {
@@ -76,7 +324,7 @@ impl DataManager {
         }
 
         // insert or update key
-        settings[key.into()] = serde_json::to_value(value)?; 
+        settings[key.into()] = serde_json::to_value(value)?;
 
         // save updated JSON
         Self::save_one_to_file(Self::SETTINGS_PATH, &settings).await
@@ -88,7 +336,7 @@ impl DataManager {
 
     pub async fn save_one_to_file<T: Serialize>(path: &str, obj: &T) -> std::io::Result<()> {
         let json = serde_json::to_string_pretty(obj)?;
-        Self::save_to_file(path, json).await 
+        Self::save_to_file(path, json).await
     }
 
     pub async fn save_many_to_file<T: Serialize>(path: &str, objs: &[T]) -> std::io::Result<()> {
@@ -97,7 +345,7 @@ impl DataManager {
     }
 
     async fn save_to_file(filename: &str, json: String) -> std::io::Result<()> {
-        let path: PathBuf = Self::get_data_path(filename);
+        let path: PathBuf = Self::get_file_path(filename);
 
         Self::ensure_file_exists(&path).await?;
 
@@ -118,15 +366,130 @@ impl DataManager {
         Self::load_from_file(Self::SETTINGS_PATH).await
     }
 
-    pub async fn load_mg_collection<MGCollection: DeserializeOwned>() -> std::io::Result<MGCollection> {
+    pub async fn load_mg_collection<MGCollection: DeserializeOwned>(
+    ) -> std::io::Result<MGCollection> {
         Self::load_from_file(Self::MG_COLLECTION_PATH).await
     }
 
+    /* Loads the collection, giving an id to any MG saved without one. */
+    pub async fn load_mg_collection_with_ids() -> std::io::Result<MGCollection> {
+        let mut mgs: MGCollection = Self::load_mg_collection::<MGCollection>().await?;
+        if mgs.iter().any(|mg| mg.id.is_empty()) {
+            mgs.iter_mut().for_each(|mg| mg.ensure_id());
+            Self::save_mg_collection(&mgs).await?;
+        }
+        Ok(mgs)
+    }
+
     pub async fn load_from_file<T: DeserializeOwned>(filename: &str) -> std::io::Result<T> {
-        let path: PathBuf = Self::get_data_path(filename);
+        let path: PathBuf = Self::get_file_path(filename);
         let contents = fs::read_to_string(path).await?;
         let data = serde_json::from_str::<T>(&contents)?;
         Ok(data)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{get_mg, get_temp_dir, lines, use_test_data_dir, DATA_DIR_LOCK};
+
+    #[test]
+    fn unversioned_mgs_keep_their_content_as_version_1() {
+        let mut mg = get_mg("English", &["Mary :: d;"]);
+        let version = mg.add_version(
+            mg.title.clone(),
+            mg.lang.clone(),
+            lines(&["John :: d;"]),
+            None,
+        );
+        assert_eq!(version, 2);
+        assert_eq!(mg.get_version(1).unwrap().grammar, lines(&["Mary :: d;"]));
+        assert_eq!(mg.grammar, lines(&["John :: d;"]));
+    }
+
+    #[test]
+    fn diff_compares_trimmed_items() {
+        let mut mg = get_mg("English", &[]);
+        mg.add_version(
+            mg.title.clone(),
+            mg.lang.clone(),
+            lines(&["Mary :: d;", "laughs :: =d v;"]),
+            Some(10.0),
+        );
+        mg.add_version(
+            String::from("English 2"),
+            mg.lang.clone(),
+            lines(&["  Mary :: d;", "", "cries :: =d v;"]),
+            Some(12.5),
+        );
+
+        let diff = mg.diff(1, 2).unwrap();
+        assert!(diff.title_changed);
+        assert!(!diff.lang_changed);
+        assert_eq!(diff.added, vec!["cries :: =d v;"]);
+        assert_eq!(diff.removed, vec!["laughs :: =d v;"]);
+        assert_eq!(diff.mdl_change, Some(2.5));
+
+        assert!(mg.diff(1, 3).is_none());
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn restoring_adds_the_old_version_again() {
+        let mut mg = get_mg("English", &[]);
+        mg.add_version(
+            mg.title.clone(),
+            mg.lang.clone(),
+            lines(&["Mary :: d;"]),
+            None,
+        );
+        mg.add_version(
+            mg.title.clone(),
+            mg.lang.clone(),
+            lines(&["John :: d;"]),
+            None,
+        );
+
+        let old = mg.get_version(1).cloned().unwrap();
+        let version = mg.add_version(old.title, old.lang, old.grammar, old.mdl);
+        assert_eq!(version, 3);
+        assert_eq!(mg.grammar, lines(&["Mary :: d;"]));
+        // nothing is lost from the history
+        assert_eq!(mg.history.len(), 3);
+        assert!(mg.diff(2, 3).unwrap().added == vec!["Mary :: d;"]);
+    }
+
+    #[test]
+    fn examples_are_valid() {
+        let examples: MGCollection = serde_json::from_str(EXAMPLE_MGS).unwrap();
+        assert!(!examples.is_empty());
+        assert!(examples.iter().all(|mg| !mg.grammar.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn legacy_data_is_migrated_on_first_run_only() {
+        use_test_data_dir();
+        let _guard = DATA_DIR_LOCK.lock().await;
+        let legacy_dir = get_temp_dir();
+        let legacy = legacy_dir.join(DataManager::MG_COLLECTION_PATH);
+        std::fs::write(&legacy, r#"[{"title":"English","lang":"en","grammar":[]}]"#).unwrap();
+        let marker = DataManager::get_data_path(DataManager::INITIALISED_PATH);
+        let collection = DataManager::get_data_path(DataManager::MG_COLLECTION_PATH);
+        assert!(!marker.exists() && !collection.exists());
+
+        DataManager::init_data_dir_from(&legacy_dir).await.unwrap();
+        assert!(marker.exists());
+        assert_eq!(
+            std::fs::read(&collection).unwrap(),
+            std::fs::read(&legacy).unwrap()
+        );
+
+        // a deleted collection isn't brought back once the directory is set up
+        std::fs::remove_file(&collection).unwrap();
+        DataManager::init_data_dir_from(&legacy_dir).await.unwrap();
+        assert!(!collection.exists());
+
+        std::fs::remove_file(marker).unwrap();
+        std::fs::remove_dir_all(legacy_dir).unwrap();
+    }
+}
@@ -0,0 +1,265 @@
+use crate::calculator::get_saved_mdl;
+use crate::data::sqlite::SqliteStore;
+use crate::data::storage::{get_timestamp, DataManager, MGCollection, MGExample, Settings};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::io;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/*
+Persistence for everything the application saves.
+Each method is a single operation so that backends can make it atomic,
+rather than handlers loading and rewriting the whole collection.
+*/
+#[async_trait]
+pub trait MGStore: Send + Sync {
+    async fn load_mg_collection(&self) -> io::Result<MGCollection>;
+
+    async fn get_mg(&self, id: &str) -> io::Result<Option<MGExample>>;
+
+    /* Inserts the MG, or replaces the MG with the same id. */
+    async fn save_mg(&self, mg: &MGExample) -> io::Result<()>;
+
+    /*
+    Inserts the MG unless one with the same title, language and grammar is saved.
+    Returns the id of the saved MG and whether it was added.
+    */
+    async fn insert_if_absent(&self, mg: &MGExample) -> io::Result<(String, bool)>;
+
+    /*
+    Makes the content the newest version of an MG, returning its number,
+    or None if there is no MG with the id.
+    */
+    async fn add_version(
+        &self,
+        id: &str,
+        title: String,
+        lang: String,
+        grammar: Vec<String>,
+        mdl: Option<f64>,
+    ) -> io::Result<Option<usize>>;
+
+    /* Saves an older version again as the newest, or None if there is no such MG or version. */
+    async fn restore_version(&self, id: &str, version: usize) -> io::Result<Option<usize>>;
+
+    /* Returns whether an MG was deleted. */
+    async fn delete_mg(&self, id: &str) -> io::Result<bool>;
+
+    async fn load_settings(&self) -> io::Result<Settings>;
+
+    async fn save_settings(&self, settings: &Settings) -> io::Result<()>;
+
+    async fn get_cached(&self, key: &str) -> io::Result<Option<Value>>;
+
+    /* Keeps at most MAX_CACHED entries, removing the oldest first. */
+    async fn set_cached(&self, key: &str, value: &Value) -> io::Result<()>;
+}
+
+// results from older versions of an analysis are never read again, so they age out
+pub const MAX_CACHED: usize = 500;
+
+/*
+Saves a grammar as a new MG, unless the same grammar has already been saved.
+Returns the id of the MG and whether it was added.
+*/
+pub async fn save_new_mg(store: &dyn MGStore, input: MGExample) -> io::Result<(String, bool)> {
+    let mut new_mg = MGExample {
+        id: String::new(),
+        title: String::new(),
+        lang: String::new(),
+        grammar: Vec::new(),
+        history: Vec::new(),
+    };
+    new_mg.ensure_id();
+    let mdl = get_saved_mdl(&input.grammar);
+    new_mg.add_version(input.title, input.lang, input.grammar, mdl);
+
+    store.insert_if_absent(&new_mg).await
+}
+
+/* Selected with MGGRAPH_STORAGE, either "sqlite" (the default) or "json". */
+pub async fn open_store() -> io::Result<Arc<dyn MGStore>> {
+    match std::env::var("MGGRAPH_STORAGE").as_deref() {
+        Ok("json") => Ok(Arc::new(JsonStore::new())),
+        _ => Ok(Arc::new(
+            SqliteStore::open(&DataManager::get_data_path(SqliteStore::DB_PATH)).await?,
+        )),
+    }
+}
+
+/* The original JSON files, with writes serialised within this process. */
+pub struct JsonStore {
+    lock: Mutex<()>,
+}
+
+impl Default for JsonStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JsonStore {
+    const CACHE_PATH: &str = "analysis_cache.json";
+
+    pub fn new() -> Self {
+        Self {
+            lock: Mutex::new(()),
+        }
+    }
+
+    async fn load_collection_unlocked(&self) -> io::Result<MGCollection> {
+        match DataManager::load_mg_collection_with_ids().await {
+            Ok(mgs) => Ok(mgs),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(MGCollection::new()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[async_trait]
+impl MGStore for JsonStore {
+    async fn load_mg_collection(&self) -> io::Result<MGCollection> {
+        let _guard = self.lock.lock().await;
+        self.load_collection_unlocked().await
+    }
+
+    async fn get_mg(&self, id: &str) -> io::Result<Option<MGExample>> {
+        let _guard = self.lock.lock().await;
+        let mgs = self.load_collection_unlocked().await?;
+        Ok(mgs.into_iter().find(|mg| mg.id == id))
+    }
+
+    async fn save_mg(&self, mg: &MGExample) -> io::Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut mgs = self.load_collection_unlocked().await?;
+        match mgs.iter_mut().find(|x| x.id == mg.id) {
+            Some(existing) => *existing = mg.clone(),
+            None => mgs.push(mg.clone()),
+        }
+        DataManager::save_mg_collection(&mgs).await
+    }
+
+    async fn insert_if_absent(&self, mg: &MGExample) -> io::Result<(String, bool)> {
+        let _guard = self.lock.lock().await;
+        let mut mgs = self.load_collection_unlocked().await?;
+        if let Some(existing) = mgs.iter().find(|x| x.is_same_grammar(mg)) {
+            return Ok((existing.id.clone(), false));
+        }
+        mgs.push(mg.clone());
+        DataManager::save_mg_collection(&mgs).await?;
+        Ok((mg.id.clone(), true))
+    }
+
+    async fn add_version(
+        &self,
+        id: &str,
+        title: String,
+        lang: String,
+        grammar: Vec<String>,
+        mdl: Option<f64>,
+    ) -> io::Result<Option<usize>> {
+        let _guard = self.lock.lock().await;
+        let mut mgs = self.load_collection_unlocked().await?;
+        let Some(mg) = mgs.iter_mut().find(|x| x.id == id) else {
+            return Ok(None);
+        };
+        let version = mg.add_version(title, lang, grammar, mdl);
+        DataManager::save_mg_collection(&mgs).await?;
+        Ok(Some(version))
+    }
+
+    async fn restore_version(&self, id: &str, version: usize) -> io::Result<Option<usize>> {
+        let _guard = self.lock.lock().await;
+        let mut mgs = self.load_collection_unlocked().await?;
+        let Some(mg) = mgs.iter_mut().find(|x| x.id == id) else {
+            return Ok(None);
+        };
+        let Some(old) = mg.get_version(version).cloned() else {
+            return Ok(None);
+        };
+        let version = mg.add_version(old.title, old.lang, old.grammar, old.mdl);
+        DataManager::save_mg_collection(&mgs).await?;
+        Ok(Some(version))
+    }
+
+    async fn delete_mg(&self, id: &str) -> io::Result<bool> {
+        let _guard = self.lock.lock().await;
+        let mut mgs = self.load_collection_unlocked().await?;
+        let n_before = mgs.len();
+        mgs.retain(|mg| mg.id != id);
+        if mgs.len() == n_before {
+            return Ok(false);
+        }
+        DataManager::save_mg_collection(&mgs).await?;
+        Ok(true)
+    }
+
+    async fn load_settings(&self) -> io::Result<Settings> {
+        DataManager::load_settings::<Settings>().await
+    }
+
+    async fn save_settings(&self, settings: &Settings) -> io::Result<()> {
+        let _guard = self.lock.lock().await;
+        DataManager::save_settings(settings).await
+    }
+
+    async fn get_cached(&self, key: &str) -> io::Result<Option<Value>> {
+        let cache: Value = DataManager::load_from_file(Self::CACHE_PATH)
+            .await
+            .unwrap_or(json!({}));
+        Ok(cache.get(key).and_then(|entry| entry.get("value")).cloned())
+    }
+
+    async fn set_cached(&self, key: &str, value: &Value) -> io::Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut cache: Value = DataManager::load_from_file(Self::CACHE_PATH)
+            .await
+            .unwrap_or(json!({}));
+        let Value::Object(entries) = &mut cache else {
+            cache = json!({ key: { "created_at": get_timestamp(), "value": value } });
+            return DataManager::save_one_to_file(Self::CACHE_PATH, &cache).await;
+        };
+        entries.insert(
+            key.to_string(),
+            json!({ "created_at": get_timestamp(), "value": value }),
+        );
+
+        while entries.len() > MAX_CACHED {
+            // entries from the same second tie, and the new one must stay
+            let oldest = entries
+                .iter()
+                .filter(|(k, _)| k.as_str() != key)
+                .min_by_key(|(_, entry)| entry["created_at"].as_u64().unwrap_or(0))
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => entries.remove(&oldest),
+                None => break,
+            };
+        }
+        DataManager::save_one_to_file(Self::CACHE_PATH, &cache).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{check_store, use_test_data_dir, DATA_DIR_LOCK};
+
+    #[tokio::test]
+    async fn json_store() {
+        let dir = use_test_data_dir();
+        let _guard = DATA_DIR_LOCK.lock().await;
+
+        check_store(&JsonStore::new()).await;
+
+        let cache: Value = DataManager::load_from_file(JsonStore::CACHE_PATH)
+            .await
+            .unwrap();
+        assert_eq!(cache.as_object().unwrap().len(), MAX_CACHED);
+        // left empty for the next test
+        for entry in std::fs::read_dir(dir).unwrap() {
+            std::fs::remove_file(entry.unwrap().path()).unwrap();
+        }
+    }
+}
@@ -0,0 +1,24 @@
+/*
+The MG engine: parsing grammars into lexical items, calculating their
+size, decomposing affixes and building state graphs, either in Neo4j
+or in memory. The HTTP API used by the app is in server, which the
+mggraph-backend binary starts.
+*/
+pub mod calculator;
+pub mod cypher;
+pub mod data;
+pub mod logging;
+pub mod parse;
+pub mod server;
+#[cfg(test)]
+mod test_utils;
+
+pub use parse::{decomp, graph, local_graph};
+
+pub use calculator::{Calculate, GrammarSizeCalculator, MDL};
+pub use parse::decomp::{Affix, Decomposer};
+pub use parse::grammar::Grammar;
+pub use parse::graph::{GrammarGraph, StateGraph};
+pub use parse::local_graph::LocalGraph;
+pub use parse::mg::{LexicalItem, MG};
+pub use parse::parser::Parser;
@@ -0,0 +1,117 @@
+use serde::Deserialize;
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer, Registry};
+
+// the server's own messages and each request, without the graph queries
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    // one object per line, as read by the desktop app
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Unknown log format {}, expected text or json", s)),
+        }
+    }
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/*
+Logs to stdout, or stderr for the command line so its output stays clean,
+and to a JSON file if one is given. Levels are written like RUST_LOG,
+e.g "info,mggraph_backend::parse=debug". Secrets are never logged as
+their Debug output is redacted.
+*/
+pub fn init(
+    level: &str,
+    format: LogFormat,
+    file: Option<&Path>,
+    to_stderr: bool,
+) -> io::Result<()> {
+    let filter = EnvFilter::try_new(level).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid log level {}: {}", level, e),
+        )
+    })?;
+
+    let console: BoxedLayer = match (format, to_stderr) {
+        // no colours when the output is piped to a file or the desktop app
+        (LogFormat::Text, false) => fmt::layer().with_ansi(io::stdout().is_terminal()).boxed(),
+        (LogFormat::Text, true) => fmt::layer()
+            .with_ansi(io::stderr().is_terminal())
+            .with_writer(io::stderr)
+            .boxed(),
+        (LogFormat::Json, false) => fmt::layer().json().boxed(),
+        (LogFormat::Json, true) => fmt::layer().json().with_writer(io::stderr).boxed(),
+    };
+    let mut layers: Vec<BoxedLayer> = vec![console];
+
+    if let Some(path) = file {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        layers.push(
+            fmt::layer()
+                .json()
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .boxed(),
+        );
+    }
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(filter)
+        .try_init()
+        .map_err(|e| io::Error::other(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_formats_are_lowercase() {
+        assert_eq!("json".parse(), Ok(LogFormat::Json));
+        assert_eq!("text".parse(), Ok(LogFormat::Text));
+        assert!("JSON".parse::<LogFormat>().is_err());
+        assert_eq!(
+            serde_json::from_str::<LogFormat>("\"json\"").unwrap(),
+            LogFormat::Json
+        );
+        assert_eq!(LogFormat::default(), LogFormat::Text);
+    }
+
+    // both fail before a subscriber is installed, so nothing global is changed
+    #[test]
+    fn invalid_levels_and_files_are_errors() {
+        let err = init("info,=[", LogFormat::Text, None, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let dir = std::env::temp_dir().join(format!("mggraph-missing-{}", uuid::Uuid::new_v4()));
+        assert!(init(
+            DEFAULT_LOG_LEVEL,
+            LogFormat::Json,
+            Some(&dir.join("log.json")),
+            false
+        )
+        .is_err());
+        assert!(EnvFilter::try_new("info,mggraph_backend::parse=debug").is_ok());
+    }
+}
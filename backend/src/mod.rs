@@ -1,5 +0,0 @@
-pub mod grammar;
-pub mod calculator;
-pub mod mg;
-pub mod cypher;
-pub mod parse;
\ No newline at end of file
@@ -0,0 +1,249 @@
+use crate::parse::mg::{Feature, LIRelation, LexicalItem, EPSILON};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Native, // Mary :: d -k;
+    Prolog, // [mary]::[d,-k].
+    Latex,  // a tabular lexicon
+}
+
+/* Renders a grammar for use outside of the application. */
+pub struct Exporter;
+
+impl Exporter {
+    pub fn export(mg: &[LexicalItem], format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Native => Self::to_native(mg),
+            ExportFormat::Prolog => Self::to_prolog(mg),
+            ExportFormat::Latex => Self::to_latex(mg),
+        }
+    }
+
+    pub fn to_native(mg: &[LexicalItem]) -> String {
+        mg.iter()
+            .map(|li| {
+                format!(
+                    "{}{} :: {}{};\n",
+                    Self::get_comments(li, None),
+                    Self::native_morph(li),
+                    Self::get_raw_bundle(&li.bundle).join(" "),
+                    Self::get_annotations(li)
+                )
+            })
+            .collect()
+    }
+
+    /* Stabler's Prolog lexicon format, silent heads are the empty list. */
+    pub fn to_prolog(mg: &[LexicalItem]) -> String {
+        mg.iter()
+            .map(|li| {
+                let words: Vec<String> = if li.silent {
+                    Vec::new()
+                } else {
+                    li.morph.split_whitespace().map(Self::prolog_word).collect()
+                };
+                let features: Vec<String> = Self::get_raw_bundle(&li.bundle)
+                    .iter()
+                    .map(|f| Self::prolog_atom(f))
+                    .collect();
+                format!(
+                    "{}[{}]::[{}].\n",
+                    Self::get_comments(li, Some("%")),
+                    words.join(","),
+                    features.join(",")
+                )
+            })
+            .collect()
+    }
+
+    pub fn to_latex(mg: &[LexicalItem]) -> String {
+        let mut latex = String::from("\\begin{tabular}{r@{ :: }l}\n");
+        for li in mg.iter() {
+            let morph = if li.silent {
+                String::from("$\\epsilon$")
+            } else {
+                format!("\\textit{{{}}}", Self::latex_escape(&li.morph))
+            };
+            let features: Vec<String> = li.bundle.iter().map(Self::latex_feature).collect();
+            latex.push_str(&Self::get_comments(li, Some("%")));
+            latex.push_str(&format!("{} & {} \\\\\n", morph, features.join(" ")));
+        }
+        latex.push_str("\\end{tabular}\n");
+        latex
+    }
+
+    fn native_morph(li: &LexicalItem) -> String {
+        if li.silent {
+            return String::from(EPSILON);
+        }
+        Self::quote_morph(&li.morph)
+    }
+
+    /* Morphs which the parser would otherwise split are quoted e.g "New York". */
+    pub fn quote_morph(morph: &str) -> String {
+        let needs_quotes = morph.contains(char::is_whitespace)
+            || ["::", ";", "%", "//"].iter().any(|p| morph.contains(p));
+        if needs_quotes {
+            format!("\"{}\"", morph)
+        } else {
+            morph.to_string()
+        }
+    }
+
+    fn get_annotations(li: &LexicalItem) -> String {
+        li.metadata
+            .iter()
+            .map(|(key, value)| {
+                if value.contains(char::is_whitespace)
+                    || [";", "%", "//", "@"].iter().any(|p| value.contains(p))
+                {
+                    format!(" @{}=\"{}\"", key, value)
+                } else {
+                    format!(" @{}={}", key, value)
+                }
+            })
+            .collect()
+    }
+
+    /* Comments on their own lines, optionally rewritten with another marker. */
+    fn get_comments(li: &LexicalItem, marker: Option<&str>) -> String {
+        li.comments
+            .iter()
+            .map(|c| match marker {
+                Some(m) => format!("{} {}\n", m, c.trim_start_matches(['%', '/']).trim()),
+                None => format!("{}\n", c),
+            })
+            .collect()
+    }
+
+    fn get_raw_bundle(bundle: &[Feature]) -> Vec<String> {
+        bundle.iter().map(|f| f.raw.clone()).collect()
+    }
+
+    /* Words which aren't plain lowercase atoms and non-ASCII features are quoted. */
+    fn prolog_atom(atom: &str) -> String {
+        let is_word = atom.chars().next().is_some_and(|c| c.is_ascii_lowercase())
+            && atom.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        let is_feature = atom.chars().next().is_some_and(|c| "=+-".contains(c))
+            && atom
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "=+-<>_*".contains(c));
+        let is_plain = is_word || is_feature;
+        if is_plain {
+            atom.to_string()
+        } else {
+            format!("'{}'", atom.replace('\'', "\\'"))
+        }
+    }
+
+    fn prolog_word(word: &str) -> String {
+        // words such as the affix -s are never treated as features
+        if word.starts_with(|c: char| "=+-".contains(c)) {
+            format!("'{}'", word.replace('\'', "\\'"))
+        } else {
+            Self::prolog_atom(word)
+        }
+    }
+
+    /* Feature ids are set in small caps with the operators in math mode e.g =\textsc{d}. */
+    fn latex_feature(f: &Feature) -> String {
+        let mut id = format!("\\textsc{{{}}}", Self::latex_escape(&f.id));
+        if let Some(restriction) = &f.restriction {
+            id.push_str(&format!(
+                "$_{{\\textsc{{{}}}}}$",
+                Self::latex_escape(restriction)
+            ));
+        }
+
+        match f.rel {
+            LIRelation::LMerge | LIRelation::LMergeInter => format!("$=${}", id),
+            LIRelation::RMerge | LIRelation::RMergeInter => format!("{}$=$", id),
+            LIRelation::LMergeHead | LIRelation::LMergeHeadInter => format!("$\\Rightarrow${}", id),
+            LIRelation::RMergeHead | LIRelation::RMergeHeadInter => format!("{}$\\Leftarrow$", id),
+            LIRelation::LAdjoin => format!("$\\approx${}", id),
+            LIRelation::RAdjoin => format!("{}$\\approx$", id),
+            LIRelation::MinusMove => format!("$-${}", id),
+            LIRelation::PersistentMinusMove => format!("$-${}$^*$", id),
+            LIRelation::PlusMove => format!("$+${}", id),
+            LIRelation::CovertPlusMove => format!("$\\oplus${}", id),
+            LIRelation::State => id,
+        }
+    }
+
+    fn latex_escape(text: &str) -> String {
+        text.chars()
+            .map(|c| match c {
+                '\\' => String::from("\\textbackslash{}"),
+                '~' => String::from("\\textasciitilde{}"),
+                '^' => String::from("\\textasciicircum{}"),
+                '&' | '%' | '$' | '#' | '_' | '{' | '}' => format!("\\{}", c),
+                _ => c.to_string(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::import::{GrammarFormat, Importer};
+
+    const GRAMMAR: &str = r#""the man" :: d -k;
+ε :: =v +k t;
+-s :: v<= +k t;
+quickly :: ≈v;
+see :: =d[acc] =d v;
+"#;
+
+    fn parse(text: &str, format: GrammarFormat) -> Vec<LexicalItem> {
+        Importer::import(text, Some(format)).unwrap().2
+    }
+
+    // what the parser keeps of each item
+    fn summarise(mg: &[LexicalItem]) -> Vec<(String, bool, Vec<String>)> {
+        mg.iter()
+            .map(|li| {
+                (
+                    li.morph.clone(),
+                    li.silent,
+                    Exporter::get_raw_bundle(&li.bundle),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn native_round_trip() {
+        let mg = parse(GRAMMAR, GrammarFormat::Native);
+        let exported = Exporter::export(&mg, ExportFormat::Native);
+        assert_eq!(exported, GRAMMAR);
+        assert_eq!(
+            summarise(&parse(&exported, GrammarFormat::Native)),
+            summarise(&mg)
+        );
+    }
+
+    #[test]
+    fn prolog_round_trip() {
+        let mg = parse(GRAMMAR, GrammarFormat::Native);
+        let exported = Exporter::export(&mg, ExportFormat::Prolog);
+        assert!(exported.starts_with("[the,man]::[d,-k].\n[]::[=v,+k,t].\n['-s']::['v<=',+k,t].\n"));
+        assert_eq!(
+            summarise(&parse(&exported, GrammarFormat::Prolog)),
+            summarise(&mg)
+        );
+    }
+
+    #[test]
+    fn latex_escapes_and_marks_silent_heads() {
+        let mg = parse("ε :: =v +k t;\nR&D :: d[acc] -wh*;", GrammarFormat::Native);
+        let latex = Exporter::export(&mg, ExportFormat::Latex);
+        assert!(latex.contains("$\\epsilon$ & $=$\\textsc{v} $+$\\textsc{k} \\textsc{t} \\\\\n"));
+        assert!(
+            latex.contains("\\textit{R\\&D} & \\textsc{d}$_{\\textsc{acc}}$ $-$\\textsc{wh}$^*$")
+        );
+    }
+}
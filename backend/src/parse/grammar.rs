@@ -1,38 +1,59 @@
+use crate::parse::mg::{is_empty_morph, Feature, EPSILON};
+use crate::parse::parser::{Parser, Statement};
 use std::collections::HashSet;
 pub struct Grammar {
     pub alphabet_size: usize,
     pub n_feature_types: usize,
     pub set_phon: Vec<String>,
-    pub set_feature_bundles: Vec<Vec<String>>
+    pub set_feature_bundles: Vec<Vec<String>>,
 }
 
 impl Grammar {
-    pub fn new(mg: &str, alphabet_size: usize, n_feature_types: usize, delim: char) -> Result<Self,&'static str> {
-        let statements: Vec<String> = Self::mg_to_statements(mg, delim);
+    /* Fails on an item without features, which can't be sized. */
+    pub fn new(mg: &str, alphabet_size: usize, n_feature_types: usize) -> Result<Self, String> {
+        let statements: Vec<Statement> = Parser::get_statements(mg);
+        if let Some(statement) = statements.iter().find(|s| s.features.trim().is_empty()) {
+            return Err(format!("{} has no features.", statement.morph));
+        }
         let (set_phon, set_feature_bundles) = Self::statements_to_sets(&statements);
 
-        if set_phon.len() == set_feature_bundles.len() {
-            Ok(Self {
-                alphabet_size,
-                n_feature_types,
-                set_phon,
-                set_feature_bundles
-            })
-        }
-        else {
-            Err("Number of items and feature bundles must match.")
-        }
+        Ok(Self {
+            alphabet_size,
+            n_feature_types,
+            set_phon,
+            set_feature_bundles,
+        })
     }
 
-    /* TODO: Handle this better, be able to add more */
+    /* The number of unique base features, including selectional restrictions. */
     pub fn get_base_size(&self) -> usize {
         let features = flatten(&self.set_feature_bundles);
-        let unique_features: HashSet<_> = features.into_iter().map(
-            |s| s.replace("-", "").replace("+", "").replace("=","").replace("<=","").replace("=>","")).collect();
+        let mut unique_features: HashSet<String> = HashSet::new();
+        for f in features.iter().map(|s| Feature::new(s, true)) {
+            unique_features.insert(f.id);
+            if let Some(restriction) = f.restriction {
+                unique_features.insert(restriction);
+            }
+        }
 
         unique_features.len()
     }
 
+    /*
+    The number of feature types used to encode the grammar.
+    Falls back on the types present in the grammar if more are used
+    than were given, e.g adjunction or covert movement.
+    */
+    pub fn get_n_feature_types(&self) -> usize {
+        let features = flatten(&self.set_feature_bundles);
+        let used_feature_types: HashSet<&str> = features
+            .iter()
+            .map(|s| Feature::new(s, true).rel.get_feature_type())
+            .collect();
+
+        self.n_feature_types.max(used_feature_types.len())
+    }
+
     pub fn _get_phon_size(&self) -> usize {
         self.set_phon.len()
     }
@@ -41,34 +62,27 @@ impl Grammar {
         flatten(&self.set_feature_bundles).len()
     }
 
-    fn mg_to_statements(mg: &str, delim: char) -> Vec<String> {
-        let result: Vec<String> = mg.split(delim)
-            .map(|s| s.to_string())
-            .filter(|s| !s.is_empty())
-            .collect();
-
-        result
-    }
-
-    fn statements_to_sets(statements: &Vec<String>) -> (Vec<String>, Vec<Vec<String>>) {
+    fn statements_to_sets(statements: &[Statement]) -> (Vec<String>, Vec<Vec<String>>) {
         let mut set_phon: Vec<String> = Vec::new();
         let mut set_feature_bundle: Vec<Vec<String>> = Vec::new();
 
         for statement in statements {
-            if let Some((phon, syn)) = statement.split_once("::") {
-                println!("Statement Success: {}", statement);
-                // Add s, the left side of the statement indicating the LI's phonological realisation
-                let phon: String = phon.trim().to_string();
-                set_phon.push(phon); 
-
-                // Add δ, the right side of the statement indicating all present features
-                let feature_bundle: Vec<String> = syn.split(' ').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
-                set_feature_bundle.push(feature_bundle)
+            // Add s, the left side of the statement indicating the LI's phonological realisation
+            // All notations of an empty morph are stored as ε
+            let phon: String = if is_empty_morph(&statement.morph) {
+                String::from(EPSILON)
             } else {
-                println!("Statement formatted incorrectly. Ignoring.");
-                println!("{}", statement);
-                continue
-            }
+                statement.morph.trim().to_string()
+            };
+            set_phon.push(phon);
+
+            // Add δ, the right side of the statement indicating all present features
+            let feature_bundle: Vec<String> = statement
+                .features
+                .split_whitespace()
+                .map(|s| s.to_string())
+                .collect();
+            set_feature_bundle.push(feature_bundle)
         }
 
         // s (phonology) :: δ (feature bundle)
@@ -82,4 +96,20 @@ fn flatten(matrix: &Vec<Vec<String>>) -> Vec<String> {
         flattened.extend(inner_vec.clone());
     }
     flattened
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items_need_features() {
+        let grammar = Grammar::new("Mary :: d -k; laugh :: =d v", 26, 4).unwrap();
+        assert_eq!(grammar.set_phon, vec!["Mary", "laugh"]);
+        assert_eq!(grammar.set_feature_bundles[0], vec!["d", "-k"]);
+
+        for mg in ["Mary ::", "Mary :: d; laugh :: ;"] {
+            assert!(Grammar::new(mg, 26, 4).is_err(), "{}", mg);
+        }
+    }
+}
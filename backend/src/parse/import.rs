@@ -0,0 +1,385 @@
+use crate::parse::export::Exporter;
+use crate::parse::mg::{LexicalItem, EPSILON, MG};
+use crate::parse::parser::Parser;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, ToSchema, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GrammarFormat {
+    Native, // Mary :: d -k;
+    Prolog, // [mary]::[d,-k].
+    Json,   // [{"phon": "Mary", "features": ["d", "-k"]}]
+    Csv,    // Mary,d -k
+}
+
+// a morph and its raw features
+type ImportedItem = (String, Vec<String>);
+
+/*
+Converts published MG fragments into the native text format, which is then
+parsed as usual so that imported grammars behave exactly like typed ones.
+*/
+pub struct Importer;
+
+impl Importer {
+    pub fn detect(text: &str) -> GrammarFormat {
+        // skip any leading comments e.g a Prolog % header
+        let trimmed: String = text
+            .lines()
+            .map(|l| l.trim())
+            .skip_while(|l| l.is_empty() || l.starts_with('%') || l.starts_with("//"))
+            .collect::<Vec<&str>>()
+            .join("\n");
+
+        // [] :: =t c is a native silent head, Prolog features are always a list
+        if trimmed.starts_with('[') && trimmed.replace(char::is_whitespace, "").contains("::[") {
+            GrammarFormat::Prolog
+        } else if serde_json::from_str::<Value>(&trimmed).is_ok() {
+            GrammarFormat::Json
+        } else if !trimmed.contains("::")
+            && trimmed.lines().any(|l| l.contains(',') || l.contains('\t'))
+        {
+            GrammarFormat::Csv
+        } else {
+            GrammarFormat::Native
+        }
+    }
+
+    pub fn import(
+        text: &str,
+        format: Option<GrammarFormat>,
+    ) -> Result<(GrammarFormat, String, Vec<LexicalItem>), Box<dyn Error>> {
+        let format = format.unwrap_or_else(|| Self::detect(text));
+        let native = Self::to_native(text, format)?;
+
+        let mut mg = MG::new();
+        Parser::convert_text_to_stored(&native, &mut mg)?;
+        Ok((format, native, mg.mg))
+    }
+
+    pub fn to_native(text: &str, format: GrammarFormat) -> Result<String, Box<dyn Error>> {
+        let items: Vec<ImportedItem> = match format {
+            GrammarFormat::Native => return Ok(text.to_string()),
+            GrammarFormat::Prolog => Self::from_prolog(text)?,
+            GrammarFormat::Json => Self::from_json(text)?,
+            GrammarFormat::Csv => Self::from_csv(text)?,
+        };
+
+        Ok(items
+            .iter()
+            .map(|(morph, features)| {
+                format!(
+                    "{} :: {};\n",
+                    Exporter::quote_morph(morph),
+                    features.join(" ")
+                )
+            })
+            .collect())
+    }
+
+    /*
+    Stabler's Prolog lexicons e.g [the,man]::[d,-k]. and []::['=v',+k,t].
+    Quoted atoms may hold commas, full stops or % e.g ['100%']::[d].
+    */
+    fn from_prolog(text: &str) -> Result<Vec<ImportedItem>, Box<dyn Error>> {
+        let mut items = Vec::new();
+        for statement in Self::prolog_statements(text) {
+            let (words, features) = match Self::split_prolog(&statement, "::")[..] {
+                [words, features] => (words, features),
+                _ => return Err(format!("Invalid Prolog lexical item: {}", statement).into()),
+            };
+
+            let words = Self::prolog_list(words)
+                .ok_or_else(|| format!("Invalid Prolog word list: {}", words))?;
+            let features = Self::prolog_list(features)
+                .ok_or_else(|| format!("Invalid Prolog feature list: {}", features))?;
+
+            let morph = if words.is_empty() {
+                String::from(EPSILON)
+            } else {
+                words.join(" ")
+            };
+            items.push((morph, features));
+        }
+        Ok(items)
+    }
+
+    /* Splits the text at each full stop ending a clause, dropping % comments. */
+    fn prolog_statements(text: &str) -> Vec<String> {
+        let mut statements = Vec::new();
+        let mut current = String::new();
+        let mut chars = text.chars();
+        let (mut in_quotes, mut depth) = (false, 0);
+        while let Some(c) = chars.next() {
+            if in_quotes {
+                current.push(c);
+                match c {
+                    '\\' => current.extend(chars.next()),
+                    '\'' => in_quotes = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '%' => {
+                    // the rest of the line is a comment
+                    if chars.by_ref().any(|c| c == '\n') {
+                        current.push('\n');
+                    }
+                }
+                '.' if depth == 0 => statements.push(std::mem::take(&mut current)),
+                _ => {
+                    match c {
+                        '\'' => in_quotes = true,
+                        '[' => depth += 1,
+                        ']' => depth -= 1,
+                        _ => {}
+                    }
+                    current.push(c);
+                }
+            }
+        }
+        statements.push(current);
+        statements
+            .into_iter()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    /* Splits at each separator outside of quoted atoms. */
+    fn split_prolog<'a>(text: &'a str, separator: &str) -> Vec<&'a str> {
+        let mut parts = Vec::new();
+        let (mut start, mut in_quotes, mut is_escaped) = (0, false, false);
+        for (i, c) in text.char_indices() {
+            if in_quotes {
+                match c {
+                    _ if is_escaped => is_escaped = false,
+                    '\\' => is_escaped = true,
+                    '\'' => in_quotes = false,
+                    _ => {}
+                }
+            } else if c == '\'' {
+                in_quotes = true;
+            } else if i >= start && text[i..].starts_with(separator) {
+                parts.push(&text[start..i]);
+                start = i + separator.len();
+            }
+        }
+        parts.push(&text[start..]);
+        parts
+    }
+
+    fn prolog_list(list: &str) -> Option<Vec<String>> {
+        let inner = list.trim().strip_prefix('[')?.strip_suffix(']')?;
+        Some(
+            Self::split_prolog(inner, ",")
+                .into_iter()
+                .map(Self::prolog_atom)
+                .filter(|atom| !atom.is_empty())
+                .collect(),
+        )
+    }
+
+    /* Unquotes an atom such as 'it\'s' or 'it''s'. */
+    fn prolog_atom(atom: &str) -> String {
+        let atom = atom.trim();
+        let Some(inner) = atom.strip_prefix('\'').and_then(|a| a.strip_suffix('\'')) else {
+            return atom.to_string();
+        };
+        let mut unquoted = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' | '\'' => unquoted.extend(chars.next()),
+                _ => unquoted.push(c),
+            }
+        }
+        unquoted
+    }
+
+    /*
+    Python toolkit exports, a list of items with a phonological form
+    (string or list of words) and features given either as strings or
+    as [type, value] pairs e.g ["sel", "v"].
+    */
+    fn from_json(text: &str) -> Result<Vec<ImportedItem>, Box<dyn Error>> {
+        let json: Value = serde_json::from_str(text)?;
+        let entries = match &json {
+            Value::Array(entries) => entries,
+            Value::Object(obj) => obj
+                .get("lexicon")
+                .or_else(|| obj.get("grammar"))
+                .and_then(|v| v.as_array())
+                .ok_or("JSON grammar must be a list or contain a \"lexicon\" list")?,
+            _ => return Err("JSON grammar must be a list of lexical items".into()),
+        };
+
+        let mut items = Vec::new();
+        for entry in entries {
+            let phon = ["phon", "morph", "word"]
+                .iter()
+                .find_map(|k| entry.get(*k))
+                .unwrap_or(&Value::Null);
+            let morph = match phon {
+                Value::String(s) if !s.is_empty() => s.clone(),
+                Value::Array(words) if !words.is_empty() => words
+                    .iter()
+                    .filter_map(|w| w.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(" "),
+                _ => String::from(EPSILON),
+            };
+
+            let features = entry
+                .get("features")
+                .and_then(|f| f.as_array())
+                .ok_or_else(|| format!("Lexical item {} has no feature list", morph))?
+                .iter()
+                .map(Self::json_feature)
+                .collect::<Result<Vec<String>, Box<dyn Error>>>()?;
+
+            items.push((morph, features));
+        }
+        Ok(items)
+    }
+
+    fn json_feature(feature: &Value) -> Result<String, Box<dyn Error>> {
+        if let Some(raw) = feature.as_str() {
+            return Ok(raw.to_string());
+        }
+
+        let pair = feature
+            .as_array()
+            .filter(|p| p.len() == 2)
+            .ok_or_else(|| format!("Invalid feature: {}", feature))?;
+        let (kind, value) = (
+            pair[0].as_str().unwrap_or(""),
+            pair[1].as_str().unwrap_or(""),
+        );
+
+        match kind {
+            "sel" => Ok(format!("={}", value)),
+            "cat" => Ok(value.to_string()),
+            "pos" => Ok(format!("+{}", value)),
+            "neg" => Ok(format!("-{}", value)),
+            "adj" => Ok(format!("≈{}", value)),
+            _ => Err(format!("Unknown feature type: {}", kind).into()),
+        }
+    }
+
+    /* One item per row, e.g Mary,d -k or tab separated. A header row is skipped. */
+    fn from_csv(text: &str) -> Result<Vec<ImportedItem>, Box<dyn Error>> {
+        let mut items = Vec::new();
+        for (i, row) in text
+            .lines()
+            // a leading tab separates an empty morph
+            .map(|l| l.trim_end())
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty())
+        {
+            let (morph, features) = row
+                .split_once('\t')
+                .or_else(|| row.split_once(','))
+                .ok_or_else(|| format!("Invalid CSV row {}: {}", i + 1, row))?;
+            let morph = morph.trim().trim_matches('"');
+            let features = features.trim().trim_matches('"');
+
+            if i == 0 && matches!(morph.to_lowercase().as_str(), "morph" | "phon" | "word") {
+                continue;
+            }
+
+            let morph = if morph.is_empty() {
+                String::from(EPSILON)
+            } else {
+                morph.to_string()
+            };
+            items.push((
+                morph,
+                features.split_whitespace().map(|f| f.to_string()).collect(),
+            ));
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn import(text: &str) -> (GrammarFormat, String) {
+        let (format, native, _) = Importer::import(text, None).unwrap();
+        (format, native)
+    }
+
+    #[test]
+    fn detects_each_format() {
+        assert_eq!(
+            Importer::detect("% Stabler\n[mary]::[d,-k]."),
+            GrammarFormat::Prolog
+        );
+        assert_eq!(Importer::detect("[] :: =t c"), GrammarFormat::Native);
+        assert_eq!(
+            Importer::detect(r#"[{"phon": "Mary", "features": ["d"]}]"#),
+            GrammarFormat::Json
+        );
+        assert_eq!(Importer::detect("Mary,d -k"), GrammarFormat::Csv);
+        assert_eq!(Importer::detect("Mary :: d -k;"), GrammarFormat::Native);
+    }
+
+    #[test]
+    fn prolog_words_and_silent_heads() {
+        let (format, native) = import("[the,man]::[d,-k]. % a DP\n[]::['=v',+k,t].");
+        assert_eq!(format, GrammarFormat::Prolog);
+        assert_eq!(native, "\"the man\" :: d -k;\nε :: =v +k t;\n");
+    }
+
+    #[test]
+    fn quoted_prolog_atoms_may_hold_separators() {
+        let (_, native) = import("['100%']::[d]. % per cent\n['a,b', 'it''s.']::['=d',c].");
+        assert_eq!(native, "\"100%\" :: d;\n\"a,b it's.\" :: =d c;\n");
+    }
+
+    #[test]
+    fn exported_prolog_round_trips() {
+        let mut mg = MG::new();
+        let grammar = "\"100%\" :: d;\na,b :: =d c;\n\"it's. done\" :: =d c;\n";
+        Parser::convert_text_to_stored(grammar, &mut mg).unwrap();
+
+        let prolog = Exporter::to_prolog(&mg.mg);
+        let (format, native, items) = Importer::import(&prolog, None).unwrap();
+        assert_eq!(format, GrammarFormat::Prolog);
+        assert_eq!(native, grammar);
+        let morphs: Vec<&str> = items.iter().map(|li| li.morph.as_str()).collect();
+        assert_eq!(morphs, vec!["100%", "a,b", "it's. done"]);
+    }
+
+    #[test]
+    fn json_string_and_pair_features() {
+        let (_, native) = import(
+            r#"{"lexicon": [
+                {"phon": ["the", "man"], "features": [["cat", "d"], ["neg", "k"]]},
+                {"phon": "", "features": ["=v", "+k", "t"]}
+            ]}"#,
+        );
+        assert_eq!(native, "\"the man\" :: d -k;\nε :: =v +k t;\n");
+    }
+
+    #[test]
+    fn json_rejects_unknown_feature_types() {
+        let text = r#"[{"phon": "Mary", "features": [["licensee", "k"]]}]"#;
+        assert!(Importer::import(text, Some(GrammarFormat::Json)).is_err());
+    }
+
+    #[test]
+    fn csv_skips_its_header() {
+        let (format, native, mg) =
+            Importer::import("morph,features\nMary,d -k\n\t=v +k t", None).unwrap();
+        assert_eq!(format, GrammarFormat::Csv);
+        assert_eq!(native, "Mary :: d -k;\nε :: =v +k t;\n");
+        assert!(mg[1].silent);
+    }
+}
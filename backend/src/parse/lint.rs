@@ -0,0 +1,290 @@
+use crate::parse::mg::{LIRelation, LexicalItem};
+use crate::parse::reach::{get_category, get_selected};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use utoipa::ToSchema;
+
+pub const DEFAULT_START_CATEGORY: &str = "c";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub enum LintKind {
+    UnmatchedSelector,    // =x with no x anywhere
+    UnmatchedLicensee,    // -x with no +x anywhere
+    MissingCategory,      // no category feature
+    FeatureAfterCategory, // anything but a licensee after the category
+    UnselectedCategory,   // x can't be selected on the way to the start category
+    InvalidOrdering,      // not selectors* licensors* category licensees*
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct LintWarning {
+    pub kind: LintKind,
+    pub li: usize,
+    pub morph: String,
+    pub feature: Option<String>,
+    pub message: String,
+}
+
+/* The position of a feature in a well-formed bundle. */
+#[derive(PartialEq, PartialOrd, Clone, Copy)]
+enum FeatureSlot {
+    Selector,
+    Licensor,
+    Category,
+    Licensee,
+}
+
+fn get_feature_slot(rel: &LIRelation) -> FeatureSlot {
+    match rel {
+        LIRelation::PlusMove | LIRelation::CovertPlusMove => FeatureSlot::Licensor,
+        LIRelation::MinusMove | LIRelation::PersistentMinusMove => FeatureSlot::Licensee,
+        LIRelation::State => FeatureSlot::Category,
+        _ => FeatureSlot::Selector,
+    }
+}
+
+/*
+Static well-formedness checks over a parsed grammar.
+None of these stop a grammar from being built, they are returned as warnings.
+*/
+pub struct Linter {
+    pub start_category: String,
+}
+
+impl Linter {
+    pub fn new(start_category: &str) -> Self {
+        Self {
+            start_category: start_category.to_string(),
+        }
+    }
+
+    pub fn lint(&self, mg: &[LexicalItem]) -> Vec<LintWarning> {
+        let mut warnings: Vec<LintWarning> = Vec::new();
+
+        // collect what is available across the whole grammar
+        let mut categories: HashSet<&str> = HashSet::new();
+        let mut selected: HashSet<&str> = HashSet::new();
+        let mut licensors: HashSet<&str> = HashSet::new();
+        for f in mg.iter().flat_map(|li| li.bundle.iter()) {
+            match get_feature_slot(&f.rel) {
+                FeatureSlot::Category => {
+                    categories.insert(f.id.as_str());
+                }
+                FeatureSlot::Selector => {
+                    selected.insert(f.id.as_str());
+                }
+                FeatureSlot::Licensor => {
+                    licensors.insert(f.id.as_str());
+                }
+                FeatureSlot::Licensee => {}
+            }
+        }
+
+        let reachable = self.get_reachable_categories(mg);
+        let mut reported_categories: HashSet<&str> = HashSet::new();
+
+        for (i, li) in mg.iter().enumerate() {
+            let warn = |kind: LintKind, feature: Option<&str>, message: String| LintWarning {
+                kind,
+                li: i,
+                morph: li.morph.clone(),
+                feature: feature.map(|f| f.to_string()),
+                message,
+            };
+
+            let is_adjunct = li.bundle.iter().any(|f| f.rel.is_adjoin());
+            let category_count = li
+                .bundle
+                .iter()
+                .filter(|f| matches!(f.rel, LIRelation::State))
+                .count();
+
+            // adjuncts output the category they adjoin to
+            if category_count == 0 && !is_adjunct {
+                warnings.push(warn(
+                    LintKind::MissingCategory,
+                    None,
+                    format!("{} has no category feature", li.morph),
+                ));
+            }
+
+            let mut seen_category = false;
+            let mut furthest_slot = FeatureSlot::Selector;
+            for f in li.bundle.iter() {
+                let slot = get_feature_slot(&f.rel);
+
+                match slot {
+                    FeatureSlot::Selector if !categories.contains(f.id.as_str()) => {
+                        warnings.push(warn(
+                            LintKind::UnmatchedSelector,
+                            Some(&f.raw),
+                            format!(
+                                "{} selects {} but no item has category {}",
+                                li.morph, f.id, f.id
+                            ),
+                        ));
+                    }
+                    FeatureSlot::Licensee if !licensors.contains(f.id.as_str()) => {
+                        warnings.push(warn(
+                            LintKind::UnmatchedLicensee,
+                            Some(&f.raw),
+                            format!(
+                                "{} has licensee {} but no item has licensor +{}",
+                                li.morph, f.raw, f.id
+                            ),
+                        ));
+                    }
+                    FeatureSlot::Category
+                        if !reachable.contains(f.id.as_str())
+                            && reported_categories.insert(f.id.as_str()) =>
+                    {
+                        let message = if selected.contains(f.id.as_str()) {
+                            format!(
+                                "category {} is only selected by items unreachable from the start category {}",
+                                f.id, self.start_category
+                            )
+                        } else {
+                            format!(
+                                "category {} is never selected and is not the start category {}",
+                                f.id, self.start_category
+                            )
+                        };
+                        warnings.push(warn(LintKind::UnselectedCategory, Some(&f.raw), message));
+                    }
+                    _ => {}
+                }
+
+                // only licensees may follow the category
+                if seen_category && slot != FeatureSlot::Licensee {
+                    warnings.push(warn(
+                        LintKind::FeatureAfterCategory,
+                        Some(&f.raw),
+                        format!("{} appears after the category of {}", f.raw, li.morph),
+                    ));
+                } else if slot < furthest_slot {
+                    warnings.push(warn(
+                        LintKind::InvalidOrdering,
+                        Some(&f.raw),
+                        format!(
+                            "{} in {} breaks the order selectors* licensors* category licensees*",
+                            f.raw, li.morph
+                        ),
+                    ));
+                }
+
+                if slot == FeatureSlot::Category {
+                    seen_category = true;
+                }
+                if slot > furthest_slot {
+                    furthest_slot = slot;
+                }
+            }
+        }
+
+        warnings
+    }
+
+    /*
+    The categories selected on the way down from the start category. Unlike
+    reach, this ignores whether they can be built, so a grammar with an
+    unproductive start doesn't report every category.
+    */
+    fn get_reachable_categories<'a>(&'a self, mg: &'a [LexicalItem]) -> HashSet<&'a str> {
+        let mut reachable: HashSet<&str> = HashSet::from([self.start_category.as_str()]);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for li in mg.iter() {
+                if get_category(li).is_some_and(|c| reachable.contains(c)) {
+                    for s in get_selected(li) {
+                        changed |= reachable.insert(s);
+                    }
+                }
+            }
+        }
+        reachable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::mg::MG;
+    use crate::parse::parser::Parser;
+
+    fn lint(grammar: &str) -> Vec<LintWarning> {
+        let mut mg = MG::new();
+        Parser::convert_text_to_stored(grammar, &mut mg).unwrap();
+        Linter::new(DEFAULT_START_CATEGORY).lint(&mg.mg)
+    }
+
+    fn kinds(grammar: &str) -> Vec<LintKind> {
+        lint(grammar).into_iter().map(|w| w.kind).collect()
+    }
+
+    #[test]
+    fn well_formed_grammar_has_no_warnings() {
+        assert!(lint("Mary :: d -k; laughs :: =d +k t; ε :: =t c;").is_empty());
+    }
+
+    #[test]
+    fn unmatched_selector() {
+        let warnings = lint("ε :: =t c");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, LintKind::UnmatchedSelector);
+        assert_eq!(warnings[0].feature.as_deref(), Some("=t"));
+    }
+
+    #[test]
+    fn unmatched_licensee() {
+        let warnings = lint("Mary :: d -k; ε :: =d c;");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, LintKind::UnmatchedLicensee);
+        assert_eq!(warnings[0].morph, "Mary");
+    }
+
+    #[test]
+    fn missing_category() {
+        assert_eq!(
+            kinds("Mary :: d; saw :: =d; ε :: =d c;"),
+            vec![LintKind::MissingCategory]
+        );
+        // adjuncts take the category they adjoin to
+        assert!(lint("quickly :: ≈c; ε :: c;").is_empty());
+    }
+
+    #[test]
+    fn feature_after_category() {
+        let warnings = lint("Mary :: d; ε :: c =d;");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, LintKind::FeatureAfterCategory);
+        assert_eq!(warnings[0].feature.as_deref(), Some("=d"));
+    }
+
+    #[test]
+    fn invalid_ordering() {
+        assert_eq!(
+            kinds("Mary :: d -k; ε :: +k =d c;"),
+            vec![LintKind::InvalidOrdering]
+        );
+    }
+
+    #[test]
+    fn unselected_category() {
+        let warnings = lint("Mary :: d; ε :: c;");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, LintKind::UnselectedCategory);
+        assert!(warnings[0].message.contains("never selected"));
+
+        // v is selected, but only by t which nothing on the way to c selects
+        let warnings = lint("laughs :: v; ε :: =v t; ε :: c;");
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings
+            .iter()
+            .all(|w| w.kind == LintKind::UnselectedCategory));
+        assert!(warnings[0]
+            .message
+            .contains("only selected by items unreachable"));
+        assert!(warnings[1].message.contains("never selected"));
+    }
+}
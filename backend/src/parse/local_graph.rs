@@ -0,0 +1,230 @@
+use crate::cypher::cquery::{Node, Relationship};
+use crate::parse::graph::StateGraph;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error;
+use utoipa::ToSchema;
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct GraphNode {
+    pub name: String,
+    // State or Interm, as labelled in Neo4j
+    pub kind: String,
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Clone, Debug, Default, ToSchema)]
+pub struct GraphSnapshot {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/*
+An in-memory state graph built exactly as it would be in Neo4j,
+for exporting a grammar's graph without a database. Redundant
+states are not merged, as that is done by a Cypher query.
+*/
+#[derive(Default)]
+pub struct LocalGraph {
+    graph: RefCell<GraphSnapshot>,
+}
+
+impl LocalGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> GraphSnapshot {
+        self.graph.borrow().clone()
+    }
+
+    /* Graphviz, with intermediate states drawn as boxes. */
+    pub fn to_dot(&self) -> String {
+        let graph = self.graph.borrow();
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+
+        let mut dot = String::from("digraph MG {\n    rankdir=LR;\n");
+        for node in graph.nodes.iter() {
+            let shape = if node.kind == "Interm" {
+                "box"
+            } else {
+                "circle"
+            };
+            let label = match node.properties.get("move").filter(|m| !m.is_empty()) {
+                Some(m) => format!("{}\\n{}", node.name, m),
+                None => node.name.clone(),
+            };
+            dot.push_str(&format!(
+                "    {} [shape={}, label={}];\n",
+                quote(&node.name),
+                shape,
+                quote(&label)
+            ));
+        }
+        for edge in graph.edges.iter() {
+            let get = |key: &str| edge.properties.get(key).map(|s| s.as_str()).unwrap_or("");
+            let mut label = get("li").to_string();
+            if !get("move").is_empty() {
+                label.push_str(&format!(" [{}]", get("move")));
+            }
+            let style = if get("head").is_empty() {
+                ""
+            } else {
+                ", color=orange"
+            };
+            dot.push_str(&format!(
+                "    {} -> {} [label={}{}];\n",
+                quote(&edge.from),
+                quote(&edge.to),
+                quote(&label),
+                style
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn has_node(graph: &GraphSnapshot, n: &Node) -> bool {
+        graph
+            .nodes
+            .iter()
+            .any(|x| x.kind == n.state_type && x.name == n.label)
+    }
+}
+
+#[async_trait(?Send)]
+impl StateGraph for LocalGraph {
+    async fn create_state(&self, n: Node) -> Result<(), Box<dyn Error>> {
+        let mut graph = self.graph.borrow_mut();
+        if !Self::has_node(&graph, &n) {
+            graph.nodes.push(GraphNode {
+                name: n.label,
+                kind: n.state_type,
+                properties: BTreeMap::from([(String::from("move"), String::new())]),
+            });
+        }
+        Ok(())
+    }
+
+    async fn connect_states(&self, rel: Relationship) -> Result<(), Box<dyn Error>> {
+        let mut graph = self.graph.borrow_mut();
+        // as in Cypher, states which don't exist aren't connected
+        if Self::has_node(&graph, &rel.node_a) && Self::has_node(&graph, &rel.node_b) {
+            graph.edges.push(GraphEdge {
+                from: rel.node_a.label,
+                to: rel.node_b.label,
+                properties: BTreeMap::from([(String::from("li"), rel.li.clone())]),
+            });
+        }
+        for edge in graph
+            .edges
+            .iter_mut()
+            .filter(|e| e.properties.get("li") == Some(&rel.li))
+        {
+            edge.properties.insert(String::from("move"), String::new());
+            edge.properties.insert(String::from("head"), String::new());
+        }
+        Ok(())
+    }
+
+    async fn set_state_property(
+        &self,
+        label_val: &str,
+        prop_key: &str,
+        prop_val: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut graph = self.graph.borrow_mut();
+        for node in graph
+            .nodes
+            .iter_mut()
+            .filter(|n| n.kind == "State" && n.name == label_val)
+        {
+            node.properties
+                .insert(prop_key.to_string(), prop_val.to_string());
+        }
+        Ok(())
+    }
+
+    async fn set_merge_property(
+        &self,
+        li_morph: &str,
+        prop_key: &str,
+        prop_val: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut graph = self.graph.borrow_mut();
+        for edge in graph
+            .edges
+            .iter_mut()
+            .filter(|e| e.properties.get("li").is_some_and(|li| li == li_morph))
+        {
+            edge.properties
+                .insert(prop_key.to_string(), prop_val.to_string());
+        }
+        Ok(())
+    }
+
+    async fn remove_redundancy(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::mg::MG;
+    use crate::parse::parser::{get_default_node, get_default_relationship, Parser};
+
+    #[tokio::test]
+    async fn states_are_added_once_and_only_connected_if_they_exist() {
+        let graph = LocalGraph::new();
+        let (d, v) = (
+            get_default_node(String::from("d")),
+            get_default_node(String::from("v")),
+        );
+        graph.create_state(d.clone()).await.unwrap();
+        graph.create_state(d.clone()).await.unwrap();
+
+        let laughs = get_default_relationship(d.clone(), v.clone(), String::from("laughs"));
+        graph.connect_states(laughs.clone()).await.unwrap();
+        assert!(graph.snapshot().edges.is_empty());
+
+        graph.create_state(v).await.unwrap();
+        graph.connect_states(laughs).await.unwrap();
+        graph
+            .set_merge_property("laughs", "move", "+k")
+            .await
+            .unwrap();
+        graph.set_state_property("d", "move", "-k").await.unwrap();
+
+        let snapshot = graph.snapshot();
+        assert_eq!(snapshot.nodes.len(), 2);
+        assert_eq!(snapshot.nodes[0].properties["move"], "-k");
+        assert_eq!(snapshot.edges.len(), 1);
+        assert_eq!(snapshot.edges[0].properties["move"], "+k");
+    }
+
+    #[tokio::test]
+    async fn dot_marks_intermediate_states_and_head_movement() {
+        let mut mg = MG::new();
+        Parser::convert_text_to_stored("see :: =d =>v t", &mut mg).unwrap();
+        let graph = LocalGraph::new();
+        Parser::convert_stored_to_graph(&mut mg, &graph)
+            .await
+            .unwrap();
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph MG {"));
+        assert!(dot.contains("\"<LI.d>\" [shape=box"));
+        assert!(dot.contains("\"t\" [shape=circle, label=\"t\"];"));
+        assert!(dot.contains("-> \"t\" [label=\"v\", color=orange];"));
+    }
+}
@@ -1,50 +1,266 @@
-use serde::{Serialize, Deserialize};
-use std::io::{BufReader, Write};
-use std::collections::HashSet;
-use std::error::Error;
-use std::fs::{File, read_to_string};
-use std::path::{PathBuf};
 use crate::data::storage::DataManager;
+use crate::parse::export::Exporter;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
 use std::fmt;
-
+use std::fs::{read_to_string, File};
+use std::io::{BufReader, Write};
+use std::path::PathBuf;
+use utoipa::ToSchema;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct State {
     pub id: String,
     pub is_intermediate: bool,
-    pub moves: Vec<String>
+    pub moves: Vec<String>,
+    // left or right head movement, empty otherwise
+    pub head: String,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
 pub struct Feature {
     pub raw: String,
     pub id: String,
-    pub rel: LIRelation
+    pub rel: LIRelation,
+    // selectional restriction e.g "acc" in =d[acc]
+    #[serde(default)]
+    pub restriction: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+impl Feature {
+    /*
+    Parses a single raw feature e.g "=>v" or "=d[acc]".
+    Merges which are not the last merge of their LI are intermediate.
+    */
+    pub fn new(raw: &str, is_last_merge: bool) -> Self {
+        let (rel, id) = LIRelation::from_raw(raw, is_last_merge);
+
+        // split off any selectional restriction e.g =d[acc]
+        let (id, restriction) = match id.strip_suffix(']').and_then(|s| s.split_once('[')) {
+            Some((id, restriction)) => (id.to_string(), Some(restriction.to_string())),
+            None => (id, None),
+        };
+
+        Self {
+            raw: raw.to_string(),
+            id,
+            rel,
+            restriction,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
 pub struct LexicalItem {
     pub morph: String,
     pub bundle: Vec<Feature>,
+    // phonologically null heads e.g ε :: =v +k t
+    // their morph is always stored as EPSILON
+    #[serde(default)]
+    pub silent: bool,
+    // % or // comments kept for exporting the grammar
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<String>,
+    // annotations e.g gloss, note, citation and tags
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+/* Metadata keys which would clash with the properties of graph edges. */
+pub const RESERVED_METADATA_KEYS: [&str; 4] = ["li", "move", "head", "name"];
+
+/* The canonical notation for an empty (ε) lexical item. */
+pub const EPSILON: &str = "ε";
+
+/*
+Notations accepted for an empty morph, e.g all of
+"ε :: =t c", "[] :: =t c" and ":: =t c" are silent heads.
+*/
+pub fn is_empty_morph(morph: &str) -> bool {
+    matches!(morph.trim(), "" | "ε" | "ϵ" | "[]" | "\"\"")
+}
+
+impl LexicalItem {
+    pub fn new(morph: &str, bundle: Vec<Feature>) -> Self {
+        if is_empty_morph(morph) {
+            Self::new_silent(bundle)
+        } else {
+            Self {
+                morph: morph.trim().to_string(),
+                bundle,
+                silent: false,
+                comments: Vec::new(),
+                metadata: BTreeMap::new(),
+            }
+        }
+    }
+
+    pub fn new_silent(bundle: Vec<Feature>) -> Self {
+        Self {
+            morph: String::from(EPSILON),
+            bundle,
+            silent: true,
+            comments: Vec::new(),
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /* Tags are stored as a comma separated list e.g @tags=passive,case */
+    pub fn get_tags(&self) -> Vec<&str> {
+        self.metadata
+            .get("tags")
+            .map(|tags| {
+                tags.split(',')
+                    .map(|t| t.trim())
+                    .filter(|t| !t.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.get_tags().contains(&tag)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
 pub enum LIRelation {
-    LMerge, // =x
-    RMerge, // x= 
-    LMergeInter, // =x where it there is more than one merge
-    RMergeInter, // x= where there is more than one merge
-    LMergeHead, // =>x
-    RMergeHead, // x<=
-    MinusMove, // -x
-    PlusMove, // +x
-    State, // x
+    LMerge,              // =x
+    RMerge,              // x=
+    LMergeInter,         // =x where it there is more than one merge
+    RMergeInter,         // x= where there is more than one merge
+    LMergeHead,          // =>x
+    RMergeHead,          // x<=
+    LMergeHeadInter,     // =>x where there is more than one merge
+    RMergeHeadInter,     // x<= where there is more than one merge
+    LAdjoin,             // ≈x or ~x
+    RAdjoin,             // x≈ or x~
+    MinusMove,           // -x
+    PersistentMinusMove, // -x* which is never checked off
+    PlusMove,            // +x
+    CovertPlusMove,      // ⊕x or (+)x
+    State,               // x
+}
+
+impl LIRelation {
+    /*
+    Classifies a raw feature, returning its relation and id.
+    ASCII fallbacks are accepted for the non-ASCII operators.
+    */
+    pub fn from_raw(feature: &str, is_last_merge: bool) -> (LIRelation, String) {
+        if let Some(stripped) = feature.strip_prefix("=>") {
+            let relation = if is_last_merge {
+                LIRelation::LMergeHead
+            } else {
+                LIRelation::LMergeHeadInter
+            };
+            (relation, stripped.to_string())
+        } else if let Some(stripped) = feature.strip_suffix("<=") {
+            let relation = if is_last_merge {
+                LIRelation::RMergeHead
+            } else {
+                LIRelation::RMergeHeadInter
+            };
+            (relation, stripped.to_string())
+        } else if let Some(stripped) = feature.strip_prefix('=') {
+            let relation = if is_last_merge {
+                LIRelation::LMerge
+            } else {
+                LIRelation::LMergeInter
+            };
+            (relation, stripped.to_string())
+        } else if let Some(stripped) = feature.strip_suffix('=') {
+            let relation = if is_last_merge {
+                LIRelation::RMerge
+            } else {
+                LIRelation::RMergeInter
+            };
+            (relation, stripped.to_string())
+        } else if let Some(stripped) = feature
+            .strip_prefix('≈')
+            .or_else(|| feature.strip_prefix('~'))
+        {
+            (LIRelation::LAdjoin, stripped.to_string())
+        } else if let Some(stripped) = feature
+            .strip_suffix('≈')
+            .or_else(|| feature.strip_suffix('~'))
+        {
+            (LIRelation::RAdjoin, stripped.to_string())
+        } else if let Some(stripped) = feature
+            .strip_prefix('⊕')
+            .or_else(|| feature.strip_prefix("(+)"))
+        {
+            (LIRelation::CovertPlusMove, stripped.to_string())
+        } else if let Some(stripped) = feature.strip_prefix('-').and_then(|s| s.strip_suffix('*')) {
+            (LIRelation::PersistentMinusMove, stripped.to_string())
+        } else if let Some(stripped) = feature.strip_prefix('-') {
+            (LIRelation::MinusMove, stripped.to_string())
+        } else if let Some(stripped) = feature.strip_prefix('+') {
+            (LIRelation::PlusMove, stripped.to_string())
+        } else {
+            (LIRelation::State, feature.to_string())
+        }
+    }
+
+    pub fn is_merge(&self) -> bool {
+        !self.is_move() && !matches!(self, LIRelation::State)
+    }
+
+    pub fn is_move(&self) -> bool {
+        matches!(
+            self,
+            LIRelation::MinusMove
+                | LIRelation::PersistentMinusMove
+                | LIRelation::PlusMove
+                | LIRelation::CovertPlusMove
+        )
+    }
+
+    pub fn is_adjoin(&self) -> bool {
+        matches!(self, LIRelation::LAdjoin | LIRelation::RAdjoin)
+    }
+
+    /*
+    The notation of the feature type, with intermediate merges
+    belonging to the same type as their final counterparts.
+    */
+    pub fn get_feature_type(&self) -> &'static str {
+        match self {
+            LIRelation::LMerge | LIRelation::LMergeInter => "=x",
+            LIRelation::RMerge | LIRelation::RMergeInter => "x=",
+            LIRelation::LMergeHead | LIRelation::LMergeHeadInter => "=>x",
+            LIRelation::RMergeHead | LIRelation::RMergeHeadInter => "x<=",
+            LIRelation::LAdjoin => "≈x",
+            LIRelation::RAdjoin => "x≈",
+            LIRelation::MinusMove => "-x",
+            LIRelation::PersistentMinusMove => "-x*",
+            LIRelation::PlusMove => "+x",
+            LIRelation::CovertPlusMove => "⊕x",
+            LIRelation::State => "x",
+        }
+    }
+
+    /* Head movement is stored on graph edges as "left" or "right". */
+    pub fn get_head_movement(&self) -> &'static str {
+        match self {
+            LIRelation::LMergeHead | LIRelation::LMergeHeadInter => "left",
+            LIRelation::RMergeHead | LIRelation::RMergeHeadInter => "right",
+            _ => "",
+        }
+    }
 }
 
 /* Create the basic data structure for storing an MG. */
 pub struct MG {
     pub mg: Vec<LexicalItem>,
-    pub states: HashSet<String>
+    pub states: HashSet<String>,
+}
+
+impl Default for MG {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MG {
@@ -76,7 +292,7 @@ impl MG {
 
         match serde_json::to_string_pretty(&self.mg) {
             Ok(json) => file.write_all(json.as_bytes())?,
-            Err(e) => eprintln!("Error serializing data to JSON: {}", e),
+            Err(e) => tracing::error!(error = %e, "Error serializing data to JSON"),
         }
         Ok(())
     }
@@ -85,13 +301,13 @@ impl MG {
         let filename: String = format!("grammar_parsed_{}.json", title);
         let path: PathBuf = DataManager::get_data_path(filename.as_str());
 
-
         let file = File::open(path)?;
         let reader = BufReader::new(file);
         let config: Vec<LexicalItem> = serde_json::from_reader(reader)?;
         Ok(config)
     }
 
+    #[allow(clippy::wrong_self_convention)]
     pub fn from_json_raw(&self, title: &str) -> Result<String, Box<dyn Error>> {
         let filename: String = format!("grammar_parsed_{}.json", title);
         let path: PathBuf = DataManager::get_data_path(filename.as_str());
@@ -99,21 +315,60 @@ impl MG {
         let json_str = read_to_string(path)?; // reads the raw JSON as text
         Ok(json_str)
     }
-
 }
 
 impl fmt::Display for MG {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut mg_as_str: String = String::from("");
-        for li in self.mg.iter() {
-            let m = li.morph.clone(); // should this be &str?
-            let fb = li.bundle.clone();
-            let fb_as_str: String = fb.iter().map(|x| x.raw.clone()).collect::<Vec<String>>().join(" ");
-
-            let li_line = format!("{} :: {};\n", m.as_str(), fb_as_str.as_str());
-            mg_as_str.push_str(li_line.as_str()); 
+        write!(f, "{}", Exporter::to_native(&self.mg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selectional_restrictions_are_split_from_the_id() {
+        let feature = Feature::new("=d[acc]", true);
+        assert!(matches!(feature.rel, LIRelation::LMerge));
+        assert_eq!(feature.id, "d");
+        assert_eq!(feature.restriction.as_deref(), Some("acc"));
+        assert_eq!(feature.raw, "=d[acc]");
+
+        assert_eq!(Feature::new("=d", true).restriction, None);
+    }
+
+    #[test]
+    fn adjunction_and_its_ascii_fallback() {
+        for raw in ["≈v", "~v"] {
+            let (rel, id) = LIRelation::from_raw(raw, true);
+            assert!(matches!(rel, LIRelation::LAdjoin));
+            assert_eq!(id, "v");
+        }
+        for raw in ["v≈", "v~"] {
+            assert!(matches!(
+                LIRelation::from_raw(raw, true).0,
+                LIRelation::RAdjoin
+            ));
+        }
+        assert!(LIRelation::LAdjoin.is_merge() && LIRelation::LAdjoin.is_adjoin());
+    }
+
+    #[test]
+    fn persistent_and_covert_movement() {
+        let (rel, id) = LIRelation::from_raw("-wh*", true);
+        assert!(matches!(rel, LIRelation::PersistentMinusMove));
+        assert_eq!(id, "wh");
+
+        for raw in ["⊕k", "(+)k"] {
+            let (rel, id) = LIRelation::from_raw(raw, true);
+            assert!(matches!(rel, LIRelation::CovertPlusMove));
+            assert_eq!(id, "k");
+            assert!(rel.is_move());
         }
-        println!("{}", mg_as_str);
-        write!(f, "{}", mg_as_str)
+        assert!(matches!(
+            LIRelation::from_raw("-k", true).0,
+            LIRelation::MinusMove
+        ));
     }
 }
@@ -1,5 +1,10 @@
+pub mod decomp;
+pub mod export;
 pub mod grammar;
+pub mod graph;
+pub mod import;
+pub mod lint;
+pub mod local_graph;
 pub mod mg;
-pub mod decomp;
 pub mod parser;
-pub mod graph;
\ No newline at end of file
+pub mod reach;
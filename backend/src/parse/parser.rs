@@ -1,20 +1,22 @@
-use super::graph::GrammarGraph;
+use super::graph::StateGraph;
 use super::mg::MG;
-use crate::parse::mg::LIRelation;
+use crate::cypher::cquery::{Node, Relationship};
 use crate::parse::mg::Feature;
+use crate::parse::mg::LIRelation;
 use crate::parse::mg::LexicalItem;
 use crate::parse::mg::State;
-use std::collections::HashMap;
+use crate::parse::mg::RESERVED_METADATA_KEYS;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
-use crate::cypher::cquery::{Node, Relationship};
+use tracing::{debug, trace, warn};
 
 pub fn get_node(label: String, state_type: String) -> Node {
-    let mut basic_node_props : HashMap<String, String> = HashMap::new();
+    let mut basic_node_props: HashMap<String, String> = HashMap::new();
     basic_node_props.insert(String::from("move"), String::from(""));
     Node {
-        state_type: state_type,
-        label: label,
-        props: Some(basic_node_props)
+        state_type,
+        label,
+        props: Some(basic_node_props),
     }
 }
 
@@ -29,122 +31,212 @@ pub fn get_intermediate_node(label: String) -> Node {
 }
 
 pub fn get_default_relationship(node_a: Node, node_b: Node, li: String) -> Relationship {
-    let mut basic_rel_props : HashMap<String, String> = HashMap::new();
+    let mut basic_rel_props: HashMap<String, String> = HashMap::new();
     basic_rel_props.insert(String::from("move"), String::from(""));
     Relationship {
         node_a,
         node_b,
         li,
-        props: basic_rel_props
+        props: basic_rel_props,
     }
 }
 
-pub struct Parser {}
+/* A single lexical item as written in the native text format. */
+pub struct Statement {
+    pub morph: String,
+    pub features: String,
+    // comments written before or alongside the item
+    pub comments: Vec<String>,
+    // annotations following the features e.g @gloss="to laugh"
+    pub metadata: BTreeMap<String, String>,
+}
 
-impl Parser {
-    pub fn convert_text_to_stored(minimalist_grammar: &str, mg_stored: &mut MG) -> Result<(), Box<dyn Error>> {
-        mg_stored.mg.clear();
-        mg_stored.states.clear();
-        let mut li: LexicalItem;
+/* Splits a line into its code and any % or // comment outside of quotes. */
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let mut in_quotes = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '%' if !in_quotes => return (&line[..i], Some(line[i..].trim())),
+            '/' if !in_quotes && line[i..].starts_with("//") => {
+                return (&line[..i], Some(line[i..].trim()))
+            }
+            _ => {}
+        }
+    }
+    (line, None)
+}
 
-        let mg_statements = minimalist_grammar
-            .split(";")
-            .filter(|l| { !l.is_empty() });
+/*
+Splits the annotations from a feature bundle, e.g
+=d v @gloss="to laugh" @tags=unergative,intransitive
+Keys must be plain identifiers so that they can be used as edge properties.
+*/
+fn split_annotations(features: &str) -> (String, BTreeMap<String, String>) {
+    let mut metadata: BTreeMap<String, String> = BTreeMap::new();
+    let Some(start) = find_outside_quotes(features, "@") else {
+        return (features.to_string(), metadata);
+    };
+
+    let mut rest = &features[start..];
+    while let Some(annotation) = rest.strip_prefix('@') {
+        let end = find_outside_quotes(annotation, " @").unwrap_or(annotation.len());
+        let (key, value) = annotation[..end]
+            .split_once('=')
+            .unwrap_or((&annotation[..end], ""));
+        let key = key.trim();
+
+        let is_valid_key = key.starts_with(|c: char| c.is_ascii_alphabetic())
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if is_valid_key && !RESERVED_METADATA_KEYS.contains(&key) {
+            metadata.insert(key.to_string(), value.trim().trim_matches('"').to_string());
+        } else {
+            warn!(
+                annotation = &annotation[..end],
+                "Ignoring invalid annotation"
+            );
+        }
+        rest = annotation[end..].trim_start();
+    }
 
-        for l in mg_statements {
-            li = LexicalItem { 
-                morph: String::from(""), 
-                bundle: Vec::new() 
-            };
+    (features[..start].trim().to_string(), metadata)
+}
 
-            // e.g laughs :: d= +k t
-            let morph_feature_split: Vec<String> = l.split("::").map(|c| c.to_string()).collect();
+/* Finds the first occurrence of a pattern outside of quotes. */
+fn find_outside_quotes(text: &str, pattern: &str) -> Option<usize> {
+    let mut in_quotes = false;
+    for (i, c) in text.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if !in_quotes && text[i..].starts_with(pattern) {
+            return Some(i);
+        }
+    }
+    None
+}
 
-            // STEP 1: process the phonological form: e.g "Mary" in "Mary" :: d -k 
-            if let Some(morph) = morph_feature_split.first() {
-                li.morph = morph.trim().to_string();
-                println!("Valid Morph: {}", li.morph);
-            }
-            else {
-                eprintln!("Invalid MG Statement: {}", l);
-                eprintln!("Error was found in phonological form parsing.")
-            }
+pub struct Parser {}
 
-            // STEP 2: parse the feature bundle e.g 'd -k' in "Mary" :: d -k
-            if let Some(features) = morph_feature_split.get(1) {
-                let individual_feature_split = features
-                    .split_whitespace()
-                    .map(|c| c.trim().to_string());
-
-                // determine whether we require any intermediate states
-                let num_features: i8 = individual_feature_split.clone().count() as i8;
-                let num_merges_required: i8 = individual_feature_split.clone()
-                    .filter(|c| c.contains("="))
-                    .count() as i8;
-
-                let num_movement_features: i8 = individual_feature_split.clone()
-                    .filter(|c| c.contains("+") || c.contains("-"))
-                    .count() as i8;
-
-                let requires_intermediate = num_merges_required > 1;
-
-
-                // STEP 3: iterate over each feature in the LI and add
-                // to the feature bundle
-                let mut is_last_selec: bool; 
-                for (i, feature) in individual_feature_split.enumerate() {
-                    is_last_selec = i as i8 - num_movement_features == 
-                        num_features-num_movement_features-1;
-                    
-                    let (relation, id) = 
-                    if let Some(stripped) = feature.strip_prefix("=>") {
-                        // need to create new relation for head merge
-
-                        // determine whether the merge is an intermediate
-                        // state or not
-                        let relation: LIRelation = if is_last_selec || !requires_intermediate {
-                                LIRelation::LMerge
-                        } 
-                        else {
-                                LIRelation::LMergeInter
-                        };
-                        (relation, stripped.to_string())
-                    }
-                    else if let Some(stripped) = feature.strip_prefix("=") {
-                        let relation: LIRelation = if is_last_selec || !requires_intermediate {
-                                LIRelation::LMerge
-                        } else {
-                                LIRelation::LMergeInter
-                        };
-                        (relation, stripped.to_string())
-                    } else if feature.ends_with("=") {
-                        let relation: LIRelation = if is_last_selec || !requires_intermediate {
-                                LIRelation::RMerge
-                        } else {
-                                LIRelation::RMergeInter
-                        };
-                        (relation, feature[..feature.len() - 1].to_string())
-                    } else if let Some(stripped) = feature.strip_prefix("-") {
-                        (LIRelation::MinusMove, stripped.to_string())
-                    } else if let Some(stripped) = feature.strip_prefix("+") {
-                        (LIRelation::PlusMove, stripped.to_string())
-                    } else {
-                        (LIRelation::State, feature.clone())
-                    };
+impl Parser {
+    /*
+    Splits grammar text into its lexical items. Items end with a semicolon or
+    a newline, and an indented line without "::" continues the previous item.
+    Morphs may be quoted to contain spaces, "::" or ";" e.g "New York" :: d;
+    Text outside of any item is ignored.
+    */
+    pub fn get_statements(minimalist_grammar: &str) -> Vec<Statement> {
+        let mut statements: Vec<Statement> = Vec::new();
+        let mut comments: Vec<String> = Vec::new();
+        let mut current: Option<Statement> = None;
+
+        for line in minimalist_grammar.lines() {
+            let (mut code, comment) = split_comment(line);
+            if let Some(c) = comment {
+                comments.push(c.to_string());
+            }
 
-                    // STEP 4: ADD FEATURE INFO TO LI
-                    li.bundle.push(Feature {
-                        raw: feature.clone(),
-                        id: id.clone(),
-                        rel: relation,
+            let is_indented = line.starts_with(char::is_whitespace);
+            let mut is_first_segment = true;
+            loop {
+                // one line can hold several items e.g Mary :: d -k; laugh :: =d v;
+                let end = find_outside_quotes(code, ";");
+                let segment = &code[..end.unwrap_or(code.len())];
+
+                if let Some(split) = find_outside_quotes(segment, "::") {
+                    statements.extend(current.take());
+                    current = Some(Statement {
+                        morph: segment[..split].trim().trim_matches('"').to_string(),
+                        features: segment[split + 2..].trim().to_string(),
+                        comments: std::mem::take(&mut comments),
+                        metadata: BTreeMap::new(),
                     });
+                } else if !segment.trim().is_empty() {
+                    match current.as_mut() {
+                        Some(statement) if is_first_segment && is_indented => {
+                            statement.features.push(' ');
+                            statement.features.push_str(segment.trim());
+                        }
+                        _ => warn!(
+                            text = segment.trim(),
+                            "Ignoring text outside of a lexical item"
+                        ),
+                    }
+                }
 
-                    println!("Valid -{}-", feature);
+                match end {
+                    Some(i) => {
+                        statements.extend(current.take());
+                        code = &code[i + 1..];
+                        is_first_segment = false;
+                    }
+                    None => break,
                 }
             }
-            else {
-                eprintln!("Invalid MG Statement: {}", l);
-                eprint!("Error was found during feature bundle parsing.")
+        }
+        statements.extend(current.take());
+
+        // comments after the final item are kept with it
+        if let Some(last) = statements.last_mut() {
+            last.comments.append(&mut comments);
+        }
+
+        for statement in statements.iter_mut() {
+            (statement.features, statement.metadata) = split_annotations(&statement.features);
+        }
+        statements
+    }
+
+    pub fn convert_text_to_stored(
+        minimalist_grammar: &str,
+        mg_stored: &mut MG,
+    ) -> Result<(), Box<dyn Error>> {
+        mg_stored.mg.clear();
+        mg_stored.states.clear();
+        let mut li: LexicalItem;
+
+        for statement in Self::get_statements(minimalist_grammar) {
+            // STEP 1: process the phonological form: e.g "Mary" in "Mary" :: d -k
+            // empty morphs (ε :: =t c or :: =t c) become silent heads
+            li = LexicalItem::new(&statement.morph, Vec::new());
+            li.comments = statement.comments;
+            li.metadata = statement.metadata;
+            trace!(morph = %li.morph, "Valid morph");
+
+            // STEP 2: parse the feature bundle e.g 'd -k' in "Mary" :: d -k
+            let features = &statement.features;
+            let individual_feature_split =
+                features.split_whitespace().map(|c| c.trim().to_string());
+
+            // determine whether we require any intermediate states
+            let relations: Vec<LIRelation> = individual_feature_split
+                .clone()
+                .map(|c| LIRelation::from_raw(&c, true).0)
+                .collect();
+            let num_features: i8 = relations.len() as i8;
+            let num_merges_required: i8 = relations
+                .iter()
+                .filter(|r| r.is_merge() && !r.is_adjoin())
+                .count() as i8;
+
+            let num_movement_features: i8 = relations.iter().filter(|r| r.is_move()).count() as i8;
+
+            let requires_intermediate = num_merges_required > 1;
+
+            // STEP 3: iterate over each feature in the LI and add
+            // to the feature bundle
+            let mut is_last_selec: bool;
+            for (i, feature) in individual_feature_split.enumerate() {
+                is_last_selec =
+                    i as i8 - num_movement_features == num_features - num_movement_features - 1;
+
+                // STEP 4: ADD FEATURE INFO TO LI
+                // e.g =>v, v<=, =d[acc], ≈v, -wh*, ⊕k
+                li.bundle.push(Feature::new(
+                    &feature,
+                    is_last_selec || !requires_intermediate,
+                ));
+
+                trace!(feature = %feature, "Valid feature");
             }
             mg_stored.mg.push(li);
         }
@@ -152,9 +244,28 @@ impl Parser {
         Ok(())
     }
 
-    pub async fn convert_stored_to_graph(mg_stored: &mut MG, mg_graph: &GrammarGraph) -> Result<GrammarGraph, Box<dyn Error>> {
+    pub async fn convert_stored_to_graph<G: StateGraph>(
+        mg_stored: &mut MG,
+        mg_graph: &G,
+    ) -> Result<(), Box<dyn Error>> {
+        Self::convert_stored_to_graph_with_progress(mg_stored, mg_graph, |_, _, _| Ok(())).await
+    }
+
+    /*
+    As convert_stored_to_graph, calling on_item(index, total, morph) before each LI is added.
+    An error from on_item stops the conversion, leaving the graph with only the LIs before it.
+    */
+    pub async fn convert_stored_to_graph_with_progress<G, F>(
+        mg_stored: &mut MG,
+        mg_graph: &G,
+        mut on_item: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        G: StateGraph,
+        F: FnMut(usize, usize, &str) -> Result<(), Box<dyn Error>>,
+    {
         let mut merge_state_indx: usize;
-        let mut final_state: Option<State>; 
+        let mut final_state: Option<State>;
         let mut intermediate_merge_states: Vec<State> = Vec::new();
         let mut bundle: &Vec<Feature>;
         let mut is_head: bool;
@@ -162,7 +273,9 @@ impl Parser {
 
         mg_stored.states.clear();
 
-        for li in &mg_stored.mg {
+        let total_lis = mg_stored.mg.len();
+        for (li_indx, li) in mg_stored.mg.iter().enumerate() {
+            on_item(li_indx, total_lis, &li.morph)?;
             let mut all_states: Vec<State> = Vec::new();
             let mut total_merges: usize = 0;
 
@@ -176,54 +289,52 @@ impl Parser {
             // add the LI as the first MERGE id
             merge_ids.push(li.clone().morph);
 
-            println!("Working on LI {}", li.morph);
+            debug!(morph = %li.morph, "Working on LI");
 
             // if the first feature is left or right merge, the LI is a head
             // we skip over adding non-heads until they appear in an LI
             // TODO: Don't skip it all together
             if let Some(first_feature) = bundle.first() {
-                is_head = matches!(first_feature.rel, LIRelation::LMerge) || 
-                    matches!(first_feature.rel, LIRelation::RMerge) || 
-                    matches!(first_feature.rel, LIRelation::LMergeInter) ||
-                    matches!(first_feature.rel, LIRelation::RMergeInter);
-                println!("LI is head? {}", is_head);
-            }
-            else {
-                eprintln!("LI Contains No Features: {}", li.morph);
+                is_head = first_feature.rel.is_merge();
+            } else {
+                debug!(morph = %li.morph, "LI contains no features");
                 continue;
             }
-            
+
             // iterate over the features of this LI by (index, feature)
-            for (i,f) in bundle.iter().enumerate() {
-                
+            for (i, f) in bundle.iter().enumerate() {
                 match f.rel {
-                    LIRelation::LMerge | 
-                    LIRelation::RMerge | 
-                    LIRelation::LMergeHead | 
-                    LIRelation::RMergeHead => { 
+                    LIRelation::LMerge
+                    | LIRelation::RMerge
+                    | LIRelation::LMergeHead
+                    | LIRelation::RMergeHead => {
                         // laugh :: *=v* +k t;
                         total_merges += 1;
                         let new_state = State {
                             id: f.id.clone(),
                             is_intermediate: false,
-                            moves: Vec::new()
+                            moves: Vec::new(),
+                            head: f.rel.get_head_movement().to_string(),
                         };
                         all_states.push(new_state.clone());
                     }
 
-                    LIRelation::LMergeInter |
-                    LIRelation::RMergeInter => { 
+                    LIRelation::LMergeInter
+                    | LIRelation::RMergeInter
+                    | LIRelation::LMergeHeadInter
+                    | LIRelation::RMergeHeadInter => {
                         // laugh at :: *=v* =v +k t;
                         total_merges += 1;
 
                         // we must update which state will be
                         // connected with the final state.
-                        merge_state_indx = total_merges-1;
+                        merge_state_indx = total_merges - 1;
 
                         let new_state = State {
                             id: f.id.clone(),
                             is_intermediate: true,
-                            moves: Vec::new()
+                            moves: Vec::new(),
+                            head: f.rel.get_head_movement().to_string(),
                         };
                         intermediate_merge_states.push(new_state.clone());
                         all_states.push(new_state.clone());
@@ -239,11 +350,25 @@ impl Parser {
                         }
                     }
 
-                    LIRelation::PlusMove | 
-                    LIRelation::MinusMove => {
+                    LIRelation::LAdjoin | LIRelation::RAdjoin => {
+                        // quickly :: ≈v;
+                        total_merges += 1;
+                        let new_state = State {
+                            id: f.id.clone(),
+                            is_intermediate: false,
+                            moves: Vec::new(),
+                            head: String::from(""),
+                        };
+                        all_states.push(new_state.clone());
+                    }
+
+                    LIRelation::PlusMove
+                    | LIRelation::MinusMove
+                    | LIRelation::PersistentMinusMove
+                    | LIRelation::CovertPlusMove => {
                         // append a movement feature to the most
                         // recent state
-                        println!("Appending move feature {}", f.id);
+                        trace!(feature = %f.id, "Appending move feature");
                         if let Some(recent_op) = all_states.last_mut() {
                             recent_op.moves.push(f.raw.clone());
                         }
@@ -253,7 +378,8 @@ impl Parser {
                         let new_state = State {
                             id: f.id.clone(),
                             is_intermediate: false,
-                            moves: Vec::new()
+                            moves: Vec::new(),
+                            head: String::from(""),
                         };
                         final_state = Some(new_state.clone());
                         all_states.push(new_state.clone());
@@ -264,22 +390,47 @@ impl Parser {
                 // any states found through selectional or categorial features
                 // are added as states to our MG
                 //
-                // TODO: 
+                // TODO:
                 // Extra states are being created here.
-                if matches!(f.rel, LIRelation::LMerge | 
-                    LIRelation::State | 
-                    LIRelation::RMerge) 
-                && !mg_stored.states.contains(f.id.as_str()) {
+                if matches!(
+                    f.rel,
+                    LIRelation::LMerge
+                        | LIRelation::State
+                        | LIRelation::RMerge
+                        | LIRelation::LMergeHead
+                        | LIRelation::RMergeHead
+                        | LIRelation::LAdjoin
+                        | LIRelation::RAdjoin
+                ) && !mg_stored.states.contains(f.id.as_str())
+                {
                     mg_stored.states.insert(f.id.to_string());
 
-                    mg_graph.create_state(
-                        get_default_node(f.id.clone())
-                    ).await?;
+                    mg_graph
+                        .create_state(get_default_node(f.id.clone()))
+                        .await?;
                 }
+            }
 
+            // adjunction does not change the category, so an adjunct
+            // without a category of its own outputs the adjoined state
+            if final_state.is_none() {
+                if let Some(adjoin) = bundle.iter().find(|x| x.rel.is_adjoin()) {
+                    let output_state = State {
+                        id: adjoin.id.clone(),
+                        is_intermediate: false,
+                        moves: Vec::new(),
+                        head: String::from(""),
+                    };
+                    final_state = Some(output_state.clone());
+                    all_states.push(output_state);
+                }
             }
 
-            println!("Number of Total States: {}", all_states.len());
+            trace!(
+                states = all_states.len(),
+                is_head,
+                "Collected the LI's states"
+            );
 
             let mut previous: String = String::from("");
             let mut non_head_state: String;
@@ -291,31 +442,31 @@ impl Parser {
             for (i, s) in all_states.iter().enumerate() {
                 let is_intermediate = s.is_intermediate;
 
-                println!("Working on state {}", s.id);
-
                 if num_states_in_li == 1 {
                     for m in s.moves.iter() {
-                        println!("Setting the move property of that state");
-                        mg_graph.set_state_property( s.id.as_str(), "move", m).await?;
+                        mg_graph
+                            .set_state_property(s.id.as_str(), "move", m)
+                            .await?;
                     }
                 }
                 // laughs :: =d +k v; Mary :: d -k
                 // IS FIRST AND NOT INTERMEDIATE
                 else if i == 0 && !is_intermediate {
                     if let Some(output_state) = final_state.take() {
-
                         let first_state: Node = get_default_node(s.id.clone());
                         let second_state: Node = get_default_node(output_state.id.clone());
-                        let connection: Relationship = get_default_relationship(
-                            first_state, 
-                            second_state, 
-                            li.morph.clone()
-                        );
+                        let connection: Relationship =
+                            get_default_relationship(first_state, second_state, li.morph.clone());
                         // connect the current state and the output state
                         mg_graph.connect_states(connection).await?;
 
                         let all_moves: String = s.moves.join(",");
-                        mg_graph.set_merge_property(&li.morph, "move", all_moves.as_str()).await?;
+                        mg_graph
+                            .set_merge_property(&li.morph, "move", all_moves.as_str())
+                            .await?;
+                        mg_graph
+                            .set_merge_property(&li.morph, "head", s.head.as_str())
+                            .await?;
                     }
                 }
                 // first operation of multiple
@@ -326,34 +477,35 @@ impl Parser {
                     non_head_state = s.id.clone();
                     new_state = format!("<LI.{}>", s.id);
 
-                    // for the active feature, we must make sure there is 
+                    // for the active feature, we must make sure there is
                     // a node for both the non-head and head.
                     // The below creates a node **if none exists**.
                     // Note: This may lead to some issues and should
                     // be more properly defined.
-                    
+
                     // mg_graph.create_state(non_head_state.as_str()).await?; //redundant?
-                    mg_graph.create_state(
-                        get_intermediate_node(new_state.clone())
-                    ).await?;
+                    mg_graph
+                        .create_state(get_intermediate_node(new_state.clone()))
+                        .await?;
 
                     // make this automatic
                     mg_stored.states.insert(new_state.clone().to_string());
 
-                    println!("Connecting two states...");
-                    println!("{}{}", non_head_state, new_state);
-                        let first_state: Node = get_default_node(non_head_state);
-                        let second_state: Node = get_intermediate_node(new_state.clone());
-                        let connection: Relationship = get_default_relationship(
-                            first_state, 
-                            second_state, 
-                            li.morph.clone()
-                        );
+                    trace!(from = %non_head_state, to = %new_state, "Connecting states");
+                    let first_state: Node = get_default_node(non_head_state);
+                    let second_state: Node = get_intermediate_node(new_state.clone());
+                    let connection: Relationship =
+                        get_default_relationship(first_state, second_state, li.morph.clone());
                     mg_graph.connect_states(connection).await?;
 
                     let all_moves: String = s.moves.join(",");
-                    mg_graph.set_merge_property(&li.morph, "move", all_moves.as_str()).await?;
-                    
+                    mg_graph
+                        .set_merge_property(&li.morph, "move", all_moves.as_str())
+                        .await?;
+                    mg_graph
+                        .set_merge_property(&li.morph, "head", s.head.as_str())
+                        .await?;
+
                     previous = new_state.to_string().clone();
                 }
                 // TIME TO LEAVE INTERMEDIATE STATES
@@ -374,27 +526,30 @@ impl Parser {
                         let final_intermediate_node: Node = get_intermediate_node(previous.clone());
                         let output_node: Node = get_default_node(output_state.id);
                         let connection: Relationship = get_default_relationship(
-                            final_intermediate_node, 
-                            output_node, 
-                            s.id.clone()
+                            final_intermediate_node,
+                            output_node,
+                            s.id.clone(),
                         );
                         mg_graph.connect_states(connection).await?;
 
                         let all_moves: String = s.moves.join(",");
-                        mg_graph.set_merge_property(s.id.as_str(), "move", all_moves.as_str()).await?;
+                        mg_graph
+                            .set_merge_property(s.id.as_str(), "move", all_moves.as_str())
+                            .await?;
+                        mg_graph
+                            .set_merge_property(s.id.as_str(), "head", s.head.as_str())
+                            .await?;
                     }
                 }
                 // NOT FIRST AND INTERMEDIATE
                 else if is_intermediate {
-
                     // <<HeadLI.LI>.LI>
-                    println!("Is Intermediate!");
                     new_state = format!("<{}.{}>", previous, s.id);
-                    println!("Creating state {}", new_state);
+                    trace!(state = %new_state, "Creating intermediate state");
 
-                    mg_graph.create_state(
-                        get_intermediate_node(new_state.clone())
-                    ).await?;
+                    mg_graph
+                        .create_state(get_intermediate_node(new_state.clone()))
+                        .await?;
 
                     // TODO
                     // Q: should inter states be stored as states internally?
@@ -408,26 +563,194 @@ impl Parser {
                     let intermediate_node_a: Node = get_intermediate_node(previous.clone());
                     let intermediate_node_b: Node = get_intermediate_node(new_state.clone());
                     let connection: Relationship = get_default_relationship(
-                        intermediate_node_a, 
-                        intermediate_node_b, 
-                        s.id.clone()
+                        intermediate_node_a,
+                        intermediate_node_b,
+                        s.id.clone(),
                     );
                     mg_graph.connect_states(connection).await?;
+                    mg_graph
+                        .set_merge_property(s.id.as_str(), "head", s.head.as_str())
+                        .await?;
 
                     for m in s.moves.iter() {
                         // heads are represented as a relationship and as such the property
                         // of a relationship is set
-                        mg_graph.set_state_property(new_state.as_str(), "move", m).await?;
+                        mg_graph
+                            .set_state_property(new_state.as_str(), "move", m)
+                            .await?;
                     }
                     previous = new_state.to_string().clone();
                 }
+            }
 
+            // carry any annotations onto the edges of this LI
+            for (key, value) in li.metadata.iter() {
+                mg_graph.set_merge_property(&li.morph, key, value).await?;
             }
         }
 
         // combine nodes which do not need to be separate
         mg_graph.remove_redundancy().await?;
 
-        Ok(mg_graph.clone())
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::local_graph::LocalGraph;
+    use crate::parse::mg::EPSILON;
+
+    fn parse(grammar: &str) -> Vec<LexicalItem> {
+        let mut mg = MG::new();
+        Parser::convert_text_to_stored(grammar, &mut mg).unwrap();
+        mg.mg
+    }
+
+    #[test]
+    fn empty_morphs_are_silent_heads() {
+        let mg = parse("ε :: =t c; [] :: =v t; \"\" :: =d v; :: =n d; ϵ :: n;");
+        assert_eq!(mg.len(), 5);
+        for li in mg.iter() {
+            assert!(li.silent);
+            assert_eq!(li.morph, EPSILON);
+        }
+    }
+
+    #[test]
+    fn pronounced_morphs_are_not_silent() {
+        let mg = parse("Mary :: d -k");
+        assert!(!mg[0].silent);
+        assert_eq!(mg[0].morph, "Mary");
+    }
+
+    #[test]
+    fn head_movement_merges() {
+        let mg = parse("-s :: =>v +k t; -ed :: v<= t;");
+        assert!(matches!(mg[0].bundle[0].rel, LIRelation::LMergeHead));
+        assert_eq!(mg[0].bundle[0].id, "v");
+        assert_eq!(mg[0].bundle[0].rel.get_head_movement(), "left");
+        assert!(matches!(mg[1].bundle[0].rel, LIRelation::RMergeHead));
+        assert_eq!(mg[1].bundle[0].id, "v");
+        assert_eq!(mg[1].bundle[0].rel.get_head_movement(), "right");
+    }
+
+    #[test]
+    fn head_movement_before_another_merge_is_intermediate() {
+        let mg = parse("-s :: =>v =d t; -ed :: v<= =d t;");
+        assert!(matches!(mg[0].bundle[0].rel, LIRelation::LMergeHeadInter));
+        assert!(matches!(mg[1].bundle[0].rel, LIRelation::RMergeHeadInter));
+        assert_eq!(mg[1].bundle[0].rel.get_head_movement(), "right");
+    }
+
+    #[test]
+    fn right_merge_is_not_head_movement() {
+        let mg = parse("see :: d= v;");
+        assert!(matches!(mg[0].bundle[0].rel, LIRelation::RMerge));
+        assert_eq!(mg[0].bundle[0].id, "d");
+        assert_eq!(mg[0].bundle[0].rel.get_head_movement(), "");
+    }
+
+    #[test]
+    fn restricted_selection_keeps_its_restriction() {
+        let mg = parse("see :: =d[acc] =d v");
+        let first = &mg[0].bundle[0];
+        assert!(matches!(first.rel, LIRelation::LMergeInter));
+        assert_eq!(first.id, "d");
+        assert_eq!(first.restriction.as_deref(), Some("acc"));
+    }
+
+    #[test]
+    fn comments_are_kept_with_the_next_item() {
+        let grammar = "% determiners\nthe :: =n d // the only one\n\n// nouns\ncat :: n\n% end";
+        let statements = Parser::get_statements(grammar);
+        assert_eq!(statements.len(), 2);
+        assert_eq!(
+            statements[0].comments,
+            vec!["% determiners", "// the only one"]
+        );
+        assert_eq!(statements[0].features, "=n d");
+        // comments after the final item stay with it
+        assert_eq!(statements[1].comments, vec!["// nouns", "% end"]);
+    }
+
+    #[test]
+    fn quoted_morphs_may_hold_separators() {
+        let statements = Parser::get_statements("\"New York\" :: d; \"a::b; 100%\" :: n;");
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0].morph, "New York");
+        assert_eq!(statements[1].morph, "a::b; 100%");
+        assert!(statements[1].comments.is_empty());
+    }
+
+    #[test]
+    fn items_end_with_a_semicolon_or_newline() {
+        let mg = parse("Mary :: d -k; laughs :: =d v\nsee :: =d\n    =d v\nstray text");
+        let morphs: Vec<&str> = mg.iter().map(|li| li.morph.as_str()).collect();
+        assert_eq!(morphs, vec!["Mary", "laughs", "see"]);
+        // an indented line continues the previous item
+        assert_eq!(mg[2].bundle.len(), 3);
+    }
+
+    #[test]
+    fn annotations_become_metadata() {
+        let mg = parse("laugh :: =d v @gloss=\"to laugh; loudly\" @tags=unergative,intransitive");
+        assert_eq!(mg[0].bundle.len(), 2);
+        assert_eq!(mg[0].metadata["gloss"], "to laugh; loudly");
+        assert_eq!(mg[0].get_tags(), vec!["unergative", "intransitive"]);
+        assert!(mg[0].has_tag("intransitive"));
+    }
+
+    #[test]
+    fn reserved_and_invalid_keys_are_ignored() {
+        let mg = parse("laugh :: v @li=x @move=y @1st=z @note-s=w @note=kept");
+        assert_eq!(mg[0].metadata.len(), 1);
+        assert_eq!(mg[0].metadata["note"], "kept");
+    }
+
+    async fn build_graph(grammar: &str) -> LocalGraph {
+        let mut mg = MG::new();
+        Parser::convert_text_to_stored(grammar, &mut mg).unwrap();
+        let graph = LocalGraph::new();
+        Parser::convert_stored_to_graph(&mut mg, &graph)
+            .await
+            .unwrap();
+        graph
+    }
+
+    #[tokio::test]
+    async fn silent_heads_are_edges_in_the_graph() {
+        let graph = build_graph(":: =t c").await;
+
+        let edges = graph.snapshot().edges;
+        assert_eq!(edges.len(), 1);
+        assert_eq!((edges[0].from.as_str(), edges[0].to.as_str()), ("t", "c"));
+        assert_eq!(edges[0].properties["li"], EPSILON);
+    }
+
+    #[tokio::test]
+    async fn head_movement_is_kept_on_the_edge() {
+        let graph = build_graph("-s :: v<= +k t").await;
+
+        let edges = graph.snapshot().edges;
+        assert_eq!((edges[0].from.as_str(), edges[0].to.as_str()), ("v", "t"));
+        assert_eq!(edges[0].properties["head"], "right");
+        assert_eq!(edges[0].properties["move"], "+k");
+    }
+
+    #[tokio::test]
+    async fn adjuncts_output_the_state_they_adjoin_to() {
+        let graph = build_graph("quickly :: ≈v").await;
+
+        let edges = graph.snapshot().edges;
+        assert_eq!(edges.len(), 1);
+        assert_eq!((edges[0].from.as_str(), edges[0].to.as_str()), ("v", "v"));
+    }
+
+    #[tokio::test]
+    async fn metadata_is_set_on_the_edges() {
+        let graph = build_graph("laughs :: =d v @gloss=laugh").await;
+        assert_eq!(graph.snapshot().edges[0].properties["gloss"], "laugh");
     }
 }
@@ -0,0 +1,257 @@
+use crate::parse::mg::{LIRelation, LexicalItem};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, ToSchema)]
+pub enum DeadReason {
+    Unproductive,      // a selected category can never be built
+    Unreachable,       // never used on the way to the start category
+    UncheckedLicensee, // no usable item can check one of its licensees
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct DeadItem {
+    pub li: usize,
+    pub morph: String,
+    pub reason: DeadReason,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct ReachabilityReport {
+    pub start_category: String,
+    pub useful: Vec<usize>,
+    pub dead: Vec<DeadItem>,
+    pub productive_categories: Vec<String>,
+    pub reachable_categories: Vec<String>,
+}
+
+/*
+Determines which lexical items can take part in a complete derivation of
+the start category, using the feature bundles alone.
+An item is useful if it is productive (everything it selects can be built),
+reachable (its category is eventually selected on the way to the start category)
+and each of its licensees can be checked by another useful item.
+*/
+pub struct Reachability {
+    pub start_category: String,
+}
+
+pub(crate) fn get_category(li: &LexicalItem) -> Option<&str> {
+    // adjuncts output the category they adjoin to
+    li.bundle
+        .iter()
+        .find(|f| matches!(f.rel, LIRelation::State))
+        .or_else(|| li.bundle.iter().find(|f| f.rel.is_adjoin()))
+        .map(|f| f.id.as_str())
+}
+
+pub(crate) fn get_selected(li: &LexicalItem) -> impl Iterator<Item = &str> {
+    li.bundle
+        .iter()
+        .filter(|f| f.rel.is_merge())
+        .map(|f| f.id.as_str())
+}
+
+impl Reachability {
+    pub fn new(start_category: &str) -> Self {
+        Self {
+            start_category: start_category.to_string(),
+        }
+    }
+
+    pub fn analyse(&self, mg: &[LexicalItem]) -> ReachabilityReport {
+        let mut alive: Vec<bool> = mg.iter().map(|li| get_category(li).is_some()).collect();
+        let mut dead: Vec<Option<DeadReason>> = alive
+            .iter()
+            .map(|x| {
+                if *x {
+                    None
+                } else {
+                    Some(DeadReason::Unproductive)
+                }
+            })
+            .collect();
+
+        let mut productive: HashSet<&str>;
+        let mut reachable: HashSet<&str>;
+
+        // removing an item can make others dead, so repeat until nothing changes
+        loop {
+            // STEP 1: bottom-up, which categories can be built
+            productive = HashSet::new();
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for (i, li) in mg.iter().enumerate() {
+                    if !alive[i] {
+                        continue;
+                    }
+                    if let Some(cat) = get_category(li) {
+                        let is_productive = get_selected(li).all(|s| productive.contains(s));
+                        if is_productive && productive.insert(cat) {
+                            changed = true;
+                        }
+                    }
+                }
+            }
+
+            // STEP 2: top-down, which categories are used from the start category
+            reachable = HashSet::new();
+            if productive.contains(self.start_category.as_str()) {
+                reachable.insert(self.start_category.as_str());
+            }
+            changed = true;
+            while changed {
+                changed = false;
+                for (i, li) in mg.iter().enumerate() {
+                    let is_used = alive[i]
+                        && get_category(li).is_some_and(|c| reachable.contains(c))
+                        && get_selected(li).all(|s| productive.contains(s));
+                    if is_used {
+                        for s in get_selected(li) {
+                            changed |= reachable.insert(s);
+                        }
+                    }
+                }
+            }
+
+            // STEP 3: licensees need a usable licensor
+            let mut licensors: HashSet<&str> = HashSet::new();
+            for (i, li) in mg.iter().enumerate() {
+                if alive[i] && get_category(li).is_some_and(|c| reachable.contains(c)) {
+                    licensors.extend(
+                        li.bundle
+                            .iter()
+                            .filter(|f| {
+                                matches!(f.rel, LIRelation::PlusMove | LIRelation::CovertPlusMove)
+                            })
+                            .map(|f| f.id.as_str()),
+                    );
+                }
+            }
+
+            let mut any_removed = false;
+            for (i, li) in mg.iter().enumerate() {
+                if !alive[i] {
+                    continue;
+                }
+                let reason = if !get_selected(li).all(|s| productive.contains(s)) {
+                    Some(DeadReason::Unproductive)
+                } else if !get_category(li).is_some_and(|c| reachable.contains(c)) {
+                    Some(DeadReason::Unreachable)
+                } else if li
+                    .bundle
+                    .iter()
+                    .filter(|f| {
+                        matches!(
+                            f.rel,
+                            LIRelation::MinusMove | LIRelation::PersistentMinusMove
+                        )
+                    })
+                    .any(|f| !licensors.contains(f.id.as_str()))
+                {
+                    Some(DeadReason::UncheckedLicensee)
+                } else {
+                    None
+                };
+
+                if reason.is_some() {
+                    alive[i] = false;
+                    dead[i] = reason;
+                    any_removed = true;
+                }
+            }
+
+            if !any_removed {
+                break;
+            }
+        }
+
+        let mut productive_categories: Vec<String> =
+            productive.iter().map(|s| s.to_string()).collect();
+        let mut reachable_categories: Vec<String> =
+            reachable.iter().map(|s| s.to_string()).collect();
+        productive_categories.sort();
+        reachable_categories.sort();
+
+        ReachabilityReport {
+            start_category: self.start_category.clone(),
+            useful: (0..mg.len()).filter(|i| alive[*i]).collect(),
+            dead: dead
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, reason)| {
+                    reason.map(|reason| DeadItem {
+                        li: i,
+                        morph: mg[i].morph.clone(),
+                        reason,
+                    })
+                })
+                .collect(),
+            productive_categories,
+            reachable_categories,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::mg::MG;
+    use crate::parse::parser::Parser;
+
+    fn analyse(grammar: &str) -> ReachabilityReport {
+        let mut mg = MG::new();
+        Parser::convert_text_to_stored(grammar, &mut mg).unwrap();
+        Reachability::new("c").analyse(&mg.mg)
+    }
+
+    fn get_reason(report: &ReachabilityReport, li: usize) -> Option<DeadReason> {
+        report
+            .dead
+            .iter()
+            .find(|d| d.li == li)
+            .map(|d| d.reason.clone())
+    }
+
+    #[test]
+    fn every_item_of_a_complete_grammar_is_useful() {
+        let report = analyse("Mary :: d -k; laughs :: =d +k t; ε :: =t c");
+        assert_eq!(report.useful, vec![0, 1, 2]);
+        assert!(report.dead.is_empty());
+        assert_eq!(report.reachable_categories, vec!["c", "d", "t"]);
+    }
+
+    #[test]
+    fn items_selecting_what_cant_be_built_are_unproductive() {
+        let report = analyse("ε :: =x c; laughs :: t; ε :: =t c");
+        assert_eq!(report.useful, vec![1, 2]);
+        assert_eq!(get_reason(&report, 0), Some(DeadReason::Unproductive));
+        assert!(!report.productive_categories.contains(&String::from("x")));
+    }
+
+    #[test]
+    fn items_never_used_from_the_start_are_unreachable() {
+        let report = analyse("laughs :: t; ε :: =t c; Mary :: d");
+        assert_eq!(report.useful, vec![0, 1]);
+        assert_eq!(get_reason(&report, 2), Some(DeadReason::Unreachable));
+    }
+
+    #[test]
+    fn pruning_repeats_until_nothing_changes() {
+        // Mary can't move, which leaves nothing of category d for the complementizer
+        let report = analyse("Mary :: d -k; ε :: =d c");
+        assert!(report.useful.is_empty());
+        assert_eq!(get_reason(&report, 0), Some(DeadReason::UncheckedLicensee));
+        assert_eq!(get_reason(&report, 1), Some(DeadReason::Unproductive));
+    }
+
+    #[test]
+    fn nothing_is_useful_without_the_start_category() {
+        let report = analyse("laughs :: t");
+        assert!(report.useful.is_empty());
+        assert_eq!(get_reason(&report, 0), Some(DeadReason::Unreachable));
+        assert!(report.reachable_categories.is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::Error;
+
+use super::error::ApiError;
+
+// answered without a token, so the desktop app can tell when the backend is up
+const PUBLIC_PATHS: [&str; 1] = ["/health"];
+
+/* The secret every request must send as "Authorization: Bearer <token>", if there is one. */
+#[derive(Clone)]
+pub struct ApiToken(pub Option<String>);
+
+/* Compares in constant time, so the token can't be guessed from response times. */
+fn is_same_token(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn get_bearer_token(req: &ServiceRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/*
+Rejects requests without the token held as app data. Errors are returned
+as responses rather than Err so that CORS headers are still added.
+*/
+pub async fn require_token<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let is_public = PUBLIC_PATHS.contains(&req.path());
+    let is_allowed = match req.app_data::<ApiToken>() {
+        Some(ApiToken(Some(expected))) => {
+            get_bearer_token(&req).is_some_and(|given| is_same_token(given, expected))
+        }
+        _ => true,
+    };
+
+    if is_public || is_allowed {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    }
+    let error = ApiError::Unauthorized(String::from("A valid API token is required."));
+    Ok(req.error_response(error).map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{middleware, web, App, HttpResponse};
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(is_same_token("secret", "secret"));
+        assert!(!is_same_token("secreT", "secret"));
+        assert!(!is_same_token("secret", "secrets"));
+        assert!(!is_same_token("", "secret"));
+    }
+
+    #[test]
+    fn only_bearer_tokens_are_read() {
+        let get = |value: &str| {
+            let req = TestRequest::default()
+                .insert_header((header::AUTHORIZATION, value))
+                .to_srv_request();
+            get_bearer_token(&req).map(String::from)
+        };
+        assert_eq!(get("Bearer secret "), Some(String::from("secret")));
+        assert_eq!(get("Basic secret"), None);
+        assert_eq!(get("bearer secret"), None);
+        assert_eq!(
+            get_bearer_token(&TestRequest::default().to_srv_request()),
+            None
+        );
+    }
+
+    #[actix_web::test]
+    async fn requests_without_the_token_are_rejected() {
+        let app = init_service(
+            App::new()
+                .app_data(ApiToken(Some(String::from("secret"))))
+                .wrap(middleware::from_fn(require_token))
+                .route("/health", web::get().to(HttpResponse::Ok))
+                .route("/mgs", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let status = |path: &str, token: Option<&str>| {
+            let mut req = TestRequest::get().uri(path);
+            if let Some(token) = token {
+                req = req.insert_header((header::AUTHORIZATION, format!("Bearer {}", token)));
+            }
+            call_service(&app, req.to_request())
+        };
+
+        assert_eq!(status("/health", None).await.status(), StatusCode::OK);
+        assert_eq!(
+            status("/mgs", None).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status("/mgs", Some("guess")).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status("/mgs", Some("secret")).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[actix_web::test]
+    async fn no_token_means_no_authentication() {
+        let app = init_service(
+            App::new()
+                .app_data(ApiToken(None))
+                .wrap(middleware::from_fn(require_token))
+                .route("/mgs", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let res = call_service(&app, TestRequest::get().uri("/mgs").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
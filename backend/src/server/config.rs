@@ -0,0 +1,250 @@
+use clap::Parser;
+use serde::Deserialize;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::data::storage::DataManager;
+use crate::logging::{LogFormat, DEFAULT_LOG_LEVEL};
+
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 8000;
+pub const DEFAULT_WORKERS: usize = 2;
+
+// read from the config directory, e.g ~/.config/mggraph/server.json
+const CONFIG_FILE: &str = "server.json";
+const HOST_ENV: &str = "MGGRAPH_HOST";
+const PORT_ENV: &str = "MGGRAPH_PORT";
+const WORKERS_ENV: &str = "MGGRAPH_WORKERS";
+// not a flag, as arguments can be seen by every user on the machine
+const TOKEN_ENV: &str = "MGGRAPH_API_TOKEN";
+const LOG_LEVEL_ENV: &str = "MGGRAPH_LOG";
+const LOG_FORMAT_ENV: &str = "MGGRAPH_LOG_FORMAT";
+const LOG_FILE_ENV: &str = "MGGRAPH_LOG_FILE";
+
+/*
+Where the server listens and how many workers it runs.
+Flags take precedence over environment variables, which take
+precedence over the config file. A port of 0 picks any free port.
+If a token is set, every request but /health must send it as a bearer token.
+Logs go to stdout, and to log_file as JSON if it is set.
+*/
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    pub workers: usize,
+    pub token: Option<String>,
+    pub log_level: String,
+    pub log_format: LogFormat,
+    pub log_file: Option<PathBuf>,
+}
+
+/* The flags of mggraph-backend, which take precedence over everything else. */
+#[derive(Parser, Debug, Default)]
+#[command(
+    name = "mggraph-backend",
+    version,
+    about = "The HTTP API behind the MG-Graph app"
+)]
+pub struct ServerArgs {
+    /// Where grammars and settings are stored, instead of the per-user data directory
+    #[arg(long)]
+    pub data_dir: Option<PathBuf>,
+    /// The address to listen on
+    #[arg(long)]
+    pub host: Option<String>,
+    /// The port to listen on, 0 for any free port
+    #[arg(long)]
+    pub port: Option<u16>,
+    /// The number of worker threads
+    #[arg(long)]
+    pub workers: Option<usize>,
+    /// A filter such as "info" or "info,mggraph_backend::parse=debug"
+    #[arg(long)]
+    pub log_level: Option<String>,
+    /// text or json
+    #[arg(long)]
+    pub log_format: Option<LogFormat>,
+    /// Also append JSON logs to this file
+    #[arg(long)]
+    pub log_file: Option<PathBuf>,
+}
+
+impl fmt::Debug for ServerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("workers", &self.workers)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .field("log_level", &self.log_level)
+            .field("log_format", &self.log_format)
+            .field("log_file", &self.log_file)
+            .finish()
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: String::from(DEFAULT_HOST),
+            port: DEFAULT_PORT,
+            workers: DEFAULT_WORKERS,
+            token: None,
+            log_level: String::from(DEFAULT_LOG_LEVEL),
+            log_format: LogFormat::default(),
+            log_file: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /* Must be called after the data directory has been set. */
+    pub fn load(args: &ServerArgs) -> io::Result<Self> {
+        Self::load_with(args, |name| std::env::var(name).ok())
+    }
+
+    /* As load, looking up environment variables with get_var. */
+    fn load_with(args: &ServerArgs, get_var: impl Fn(&str) -> Option<String>) -> io::Result<Self> {
+        // an empty variable counts as unset
+        let get_env = |name: &str| get_var(name).filter(|v| !v.is_empty());
+        let mut config = Self::from_file()?;
+
+        if let Some(host) = get_env(HOST_ENV) {
+            config.host = host;
+        }
+        if let Some(port) = get_env(PORT_ENV) {
+            config.port = parse_value(PORT_ENV, &port)?;
+        }
+        if let Some(workers) = get_env(WORKERS_ENV) {
+            config.workers = parse_value(WORKERS_ENV, &workers)?;
+        }
+
+        if let Some(token) = get_env(TOKEN_ENV) {
+            config.token = Some(token);
+        }
+        if let Some(level) = get_env(LOG_LEVEL_ENV) {
+            config.log_level = level;
+        }
+        if let Some(format) = get_env(LOG_FORMAT_ENV) {
+            config.log_format = parse_value(LOG_FORMAT_ENV, &format)?;
+        }
+        if let Some(file) = get_env(LOG_FILE_ENV) {
+            config.log_file = Some(PathBuf::from(file));
+        }
+
+        if let Some(host) = &args.host {
+            config.host = host.clone();
+        }
+        if let Some(port) = args.port {
+            config.port = port;
+        }
+        if let Some(workers) = args.workers {
+            config.workers = workers;
+        }
+        if let Some(level) = &args.log_level {
+            config.log_level = level.clone();
+        }
+        if let Some(format) = args.log_format {
+            config.log_format = format;
+        }
+        if let Some(file) = &args.log_file {
+            config.log_file = Some(file.clone());
+        }
+
+        // an empty token in the file turns authentication off, as if it wasn't there
+        config.token = config.token.filter(|t| !t.trim().is_empty());
+
+        if config.workers == 0 {
+            return Err(invalid("The number of workers must be at least 1."));
+        }
+        Ok(config)
+    }
+
+    fn from_file() -> io::Result<Self> {
+        let path = DataManager::get_config_path(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(&path)?;
+        serde_json::from_str(&contents)
+            .map_err(|e| invalid(&format!("Invalid {}: {}", path.display(), e)))
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> io::Result<T> {
+    value
+        .parse()
+        .map_err(|_| invalid(&format!("Invalid value for {}: {}", name, value)))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{use_test_data_dir, DATA_DIR_LOCK};
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn flags_then_file() {
+        use_test_data_dir();
+        let _guard = DATA_DIR_LOCK.lock().await;
+        let path = DataManager::get_config_path(CONFIG_FILE);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, r#"{"port": 1, "workers": 3, "token": " "}"#).unwrap();
+
+        let config = ServerConfig::load(&ServerArgs::default()).unwrap();
+        assert_eq!((config.port, config.workers), (1, 3));
+        assert_eq!(config.host, DEFAULT_HOST);
+        // a blank token turns authentication off
+        assert!(config.token.is_none());
+
+        std::fs::write(&path, r#"{"token": "secret"}"#).unwrap();
+        let config = ServerConfig::load(&ServerArgs::default()).unwrap();
+        assert_eq!(config.token.as_deref(), Some("secret"));
+        assert!(!format!("{:?}", config).contains("secret"));
+
+        let args = ServerArgs::try_parse_from(["mggraph-backend", "--port", "3"]).unwrap();
+        let config = ServerConfig::load(&args).unwrap();
+        assert_eq!((config.port, config.workers), (3, DEFAULT_WORKERS));
+
+        let args = ServerArgs::try_parse_from(["mggraph-backend", "--workers=0"]).unwrap();
+        assert!(ServerConfig::load(&args).is_err());
+
+        // the environment overrides the file, and flags override the environment
+        std::fs::write(&path, r#"{"port": 1, "host": "0.0.0.0"}"#).unwrap();
+        let env = HashMap::from([(PORT_ENV, "2"), (HOST_ENV, ""), (TOKEN_ENV, "secret")]);
+        let get_var = |name: &str| env.get(name).map(|v| v.to_string());
+        let config = ServerConfig::load_with(&ServerArgs::default(), get_var).unwrap();
+        assert_eq!((config.port, config.host.as_str()), (2, "0.0.0.0"));
+        assert_eq!(config.token.as_deref(), Some("secret"));
+
+        let args = ServerArgs::try_parse_from(["mggraph-backend", "--port", "3"]).unwrap();
+        assert_eq!(ServerConfig::load_with(&args, get_var).unwrap().port, 3);
+
+        let env = HashMap::from([(WORKERS_ENV, "many")]);
+        let get_var = |name: &str| env.get(name).map(|v| v.to_string());
+        assert!(ServerConfig::load_with(&ServerArgs::default(), get_var).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unknown_flags_and_values_are_rejected() {
+        for args in [
+            vec!["mggraph-backend", "--bogus"],
+            vec!["mggraph-backend", "--port"],
+            vec!["mggraph-backend", "--port", "http"],
+            vec!["mggraph-backend", "--log-format", "xml"],
+        ] {
+            assert!(ServerArgs::try_parse_from(args).is_err());
+        }
+        let args = ServerArgs::try_parse_from(["mggraph-backend", "--log-format", "json"]).unwrap();
+        assert_eq!(args.log_format, Some(LogFormat::Json));
+    }
+}
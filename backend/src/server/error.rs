@@ -0,0 +1,170 @@
+use actix_web::{error::ResponseError, http::StatusCode, HttpResponse};
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use utoipa::ToSchema;
+
+/*
+Every failed request is answered with the same JSON body,
+e.g {"code": "NOT_FOUND", "error": "No MG with id 1"}, so the UI
+can show the message and decide what to do from the code.
+*/
+#[derive(Debug)]
+pub enum ApiError {
+    Parse(String),        // the grammar could not be parsed
+    InvalidInput(String), // the request body or query could not be read
+    NotFound(String),
+    Unauthorized(String),     // no valid API token was sent
+    GraphUnavailable(String), // Neo4j isn't configured or can't be reached
+    Graph(String),            // a query on the graph failed
+    Storage(String),          // the saved collection, settings or secrets
+    Cancelled(String),        // a job stopped because it was cancelled
+    Internal(String),
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct ErrorResponse {
+    #[schema(example = "NOT_FOUND")]
+    pub code: &'static str,
+    pub error: String,
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Parse(_) => "PARSE_ERROR",
+            ApiError::InvalidInput(_) => "INVALID_INPUT",
+            ApiError::NotFound(_) => "NOT_FOUND",
+            ApiError::Unauthorized(_) => "UNAUTHORIZED",
+            ApiError::GraphUnavailable(_) => "GRAPH_UNAVAILABLE",
+            ApiError::Graph(_) => "GRAPH_ERROR",
+            ApiError::Storage(_) => "STORAGE_ERROR",
+            ApiError::Cancelled(_) => "CANCELLED",
+            ApiError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::Parse(m)
+            | ApiError::InvalidInput(m)
+            | ApiError::NotFound(m)
+            | ApiError::Unauthorized(m)
+            | ApiError::GraphUnavailable(m)
+            | ApiError::Graph(m)
+            | ApiError::Storage(m)
+            | ApiError::Cancelled(m)
+            | ApiError::Internal(m) => m,
+        }
+    }
+
+    /* e.g .map_err(ApiError::parse("Invalid grammar")) */
+    pub fn parse<E: fmt::Display>(context: &str) -> impl FnOnce(E) -> Self + '_ {
+        move |e| ApiError::Parse(format!("{}: {}", context, e))
+    }
+
+    pub fn graph<E: fmt::Display>(context: &str) -> impl FnOnce(E) -> Self + '_ {
+        move |e| ApiError::Graph(format!("{}: {}", context, e))
+    }
+
+    pub fn storage<E: fmt::Display>(context: &str) -> impl FnOnce(E) -> Self + '_ {
+        move |e| ApiError::Storage(format!("{}: {}", context, e))
+    }
+
+    pub fn internal<E: fmt::Display>(context: &str) -> impl FnOnce(E) -> Self + '_ {
+        move |e| ApiError::Internal(format!("{}: {}", context, e))
+    }
+
+    /* The body sent to the client, also kept as the error of a failed job. */
+    pub fn to_response(&self) -> ErrorResponse {
+        ErrorResponse {
+            code: self.code(),
+            error: self.message().to_string(),
+        }
+    }
+
+    pub fn graph_unavailable() -> Self {
+        ApiError::GraphUnavailable(String::from(
+            "Not connected to Neo4j. Check the database settings.",
+        ))
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+impl Error for ApiError {}
+
+impl From<io::Error> for ApiError {
+    fn from(e: io::Error) -> Self {
+        ApiError::Storage(e.to_string())
+    }
+}
+
+/* Lets the Tauri commands return the same errors as the HTTP API. */
+impl From<ApiError> for ErrorResponse {
+    fn from(e: ApiError) -> Self {
+        e.to_response()
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Parse(_) | ApiError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::GraphUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Cancelled(_) => StatusCode::CONFLICT,
+            ApiError::Graph(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Storage(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // server side failures are logged here rather than in each handler
+        if self.status_code().is_server_error() {
+            tracing::error!(code = self.code(), "{}", self.message());
+        }
+        HttpResponse::build(self.status_code()).json(self.to_response())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use serde_json::{json, Value};
+
+    #[actix_web::test]
+    async fn errors_are_sent_as_json() {
+        let response = ApiError::NotFound(String::from("No MG with id 1")).error_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({"code": "NOT_FOUND", "error": "No MG with id 1"})
+        );
+    }
+
+    #[test]
+    fn helpers_add_their_context() {
+        let error = Err::<(), _>("unexpected ;").map_err(ApiError::parse("Invalid grammar"));
+        let error = error.unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(error.message(), "Invalid grammar: unexpected ;");
+
+        let error = ApiError::from(io::Error::other("disk full"));
+        assert_eq!(error.code(), "STORAGE_ERROR");
+        assert!(error.status_code().is_server_error());
+        assert_eq!(
+            ApiError::graph_unavailable().status_code(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+}
@@ -0,0 +1,473 @@
+use actix_web::{get, http::header, post, web, HttpResponse};
+use futures_util::stream;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::broadcast::{self, error::RecvError};
+use utoipa::ToSchema;
+
+use super::error::{ApiError, ErrorResponse};
+use super::{run_build_mg, run_decompose, DecomposeInput, GrammarInput, MGState};
+use crate::data::storage::get_timestamp;
+
+// finished jobs are forgotten once there are more than this
+const MAX_FINISHED_JOBS: usize = 50;
+const EVENT_BUFFER: usize = 64;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    BuildGraph,
+    Decompose,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobStatus::Running)
+    }
+}
+
+/* The state of a job, as returned by /jobs/{id} and sent with every event. */
+#[derive(Serialize, Clone, ToSchema)]
+pub struct JobInfo {
+    pub id: String,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    // from 0 to 1
+    pub progress: f64,
+    pub message: String,
+    pub created_at: u64,
+    // what the equivalent endpoint would have returned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+struct JobEntry {
+    info: JobInfo,
+    events: broadcast::Sender<JobInfo>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/*
+Long operations run as tasks on the worker which started them,
+as graph queries can't be moved between threads. Each change to
+a job is broadcast to anyone following its events.
+Cancelling only asks a job to stop, which it does at the next point where
+stopping leaves the current MG whole, so the job may still complete.
+Enumerating a grammar's language and parsing a corpus have no jobs, as
+there is no derivation or recognition in the engine yet to run them.
+*/
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    jobs: Arc<Mutex<HashMap<String, JobEntry>>>,
+}
+
+impl JobRegistry {
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, JobEntry>> {
+        // a panic while holding the lock can't leave a job half updated
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn start<F, Fut>(&self, kind: JobKind, run: F) -> JobInfo
+    where
+        F: FnOnce(JobProgress) -> Fut,
+        Fut: Future<Output = Result<Value, ApiError>> + 'static,
+    {
+        self.prune();
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let info = JobInfo {
+            id: id.clone(),
+            kind,
+            status: JobStatus::Running,
+            progress: 0.0,
+            message: String::from("Started"),
+            created_at: get_timestamp(),
+            result: None,
+            error: None,
+        };
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.lock().insert(
+            id.clone(),
+            JobEntry {
+                info: info.clone(),
+                events,
+                cancelled: cancelled.clone(),
+            },
+        );
+
+        let job = run(JobProgress {
+            job: Some((id.clone(), self.clone())),
+            cancelled,
+        });
+        let registry = self.clone();
+        actix_web::rt::spawn(async move {
+            let outcome = job.await;
+            registry.finish(&id, outcome);
+        });
+        info
+    }
+
+    /* Changes a running job and tells its followers, finished jobs are left as they are. */
+    fn update(&self, id: &str, change: impl FnOnce(&mut JobInfo)) {
+        let mut jobs = self.lock();
+        let Some(entry) = jobs.get_mut(id).filter(|e| !e.info.status.is_finished()) else {
+            return;
+        };
+        change(&mut entry.info);
+        // there may be no one following
+        let _ = entry.events.send(entry.info.clone());
+    }
+
+    fn finish(&self, id: &str, outcome: Result<Value, ApiError>) {
+        self.update(id, |info| match outcome {
+            Ok(result) => {
+                info.status = JobStatus::Completed;
+                info.progress = 1.0;
+                info.message = String::from("Completed");
+                info.result = Some(result);
+            }
+            Err(ApiError::Cancelled(message)) => {
+                info.status = JobStatus::Cancelled;
+                info.message = message;
+            }
+            Err(e) => {
+                info.status = JobStatus::Failed;
+                info.message = e.message().to_string();
+                info.error = Some(e.to_response());
+            }
+        });
+    }
+
+    /* The job finishes as cancelled once it next checks, see JobProgress::check. */
+    pub fn cancel(&self, id: &str) -> Option<JobInfo> {
+        self.lock()
+            .get(id)?
+            .cancelled
+            .store(true, Ordering::Relaxed);
+        self.update(id, |info| info.message = String::from("Cancelling"));
+        self.get(id)
+    }
+
+    pub fn get(&self, id: &str) -> Option<JobInfo> {
+        self.lock().get(id).map(|e| e.info.clone())
+    }
+
+    /* Newest first. */
+    pub fn list(&self) -> Vec<JobInfo> {
+        let mut jobs: Vec<JobInfo> = self.lock().values().map(|e| e.info.clone()).collect();
+        jobs.sort_by_key(|j| std::cmp::Reverse(j.created_at));
+        jobs
+    }
+
+    /* The current state, and every change after it. */
+    pub fn subscribe(&self, id: &str) -> Option<(JobInfo, broadcast::Receiver<JobInfo>)> {
+        self.lock()
+            .get(id)
+            .map(|e| (e.info.clone(), e.events.subscribe()))
+    }
+
+    fn prune(&self) {
+        let mut jobs = self.lock();
+        let mut finished: Vec<(u64, String)> = jobs
+            .values()
+            .filter(|e| e.info.status.is_finished())
+            .map(|e| (e.info.created_at, e.info.id.clone()))
+            .collect();
+        if finished.len() < MAX_FINISHED_JOBS {
+            return;
+        }
+        finished.sort();
+        for (_, id) in finished.iter().take(finished.len() + 1 - MAX_FINISHED_JOBS) {
+            jobs.remove(id);
+        }
+    }
+}
+
+/* Passed to long operations so they can report how far along they are. */
+#[derive(Clone)]
+pub struct JobProgress {
+    job: Option<(String, JobRegistry)>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl JobProgress {
+    /* For running an operation directly, without a job to report to. */
+    pub fn none() -> Self {
+        Self {
+            job: None,
+            cancelled: Arc::default(),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /* Called between stages, stops the operation if the job has been cancelled. */
+    pub fn check(&self) -> Result<(), ApiError> {
+        if self.is_cancelled() {
+            return Err(ApiError::Cancelled(String::from("Cancelled")));
+        }
+        Ok(())
+    }
+
+    pub fn report(&self, progress: f64, message: &str) {
+        if let Some((id, registry)) = &self.job {
+            registry.update(id, |info| {
+                info.progress = progress.clamp(0.0, 1.0);
+                info.message = message.to_string();
+            });
+        }
+    }
+}
+
+fn no_such_job(id: &str) -> ApiError {
+    ApiError::NotFound(format!("No job with id {}", id))
+}
+
+fn to_value<T: Serialize>(result: T) -> Result<Value, ApiError> {
+    serde_json::to_value(result).map_err(ApiError::internal("Job result could not be serialised"))
+}
+
+/* Parses the grammar, then builds its graph in the background. The result is the same as /build-initial-mg. */
+#[utoipa::path(
+    tag = "jobs",
+    responses(
+        (status = 202, description = "The job was started", body = JobInfo),
+    )
+)]
+#[post("/jobs/build-graph")]
+async fn start_build_graph(
+    data: web::Data<MGState>,
+    input: web::Json<GrammarInput>,
+) -> HttpResponse {
+    let state = data.clone();
+    let info = data
+        .jobs
+        .start(JobKind::BuildGraph, move |progress| async move {
+            to_value(run_build_mg(&state, &input, &progress).await?)
+        });
+    HttpResponse::Accepted().json(info)
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    responses(
+        (status = 202, description = "The job was started", body = JobInfo),
+    )
+)]
+#[post("/jobs/decompose")]
+async fn start_decompose(
+    data: web::Data<MGState>,
+    input: web::Json<DecomposeInput>,
+) -> HttpResponse {
+    let state = data.clone();
+    let info = data
+        .jobs
+        .start(JobKind::Decompose, move |progress| async move {
+            to_value(run_decompose(&state, &input, &progress).await?)
+        });
+    HttpResponse::Accepted().json(info)
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    responses(
+        (status = 200, description = "Recent jobs, newest first", body = Vec<JobInfo>),
+    )
+)]
+#[get("/jobs")]
+async fn list_jobs(data: web::Data<MGState>) -> HttpResponse {
+    HttpResponse::Ok().json(data.jobs.list())
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    params(("id" = String, Path, description = "The id of the job")),
+    responses(
+        (status = 200, description = "The state of the job", body = JobInfo),
+        (status = 404, description = "No job with the id", body = ErrorResponse),
+    )
+)]
+#[get("/jobs/{id}")]
+async fn get_job(
+    data: web::Data<MGState>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let info = data.jobs.get(&id).ok_or_else(|| no_such_job(&id))?;
+    Ok(HttpResponse::Ok().json(info))
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    params(("id" = String, Path, description = "The id of the job")),
+    responses(
+        (status = 200, description = "The job, which stops at its next safe point unless it had already finished", body = JobInfo),
+        (status = 404, description = "No job with the id", body = ErrorResponse),
+    )
+)]
+#[post("/jobs/{id}/cancel")]
+async fn cancel_job(
+    data: web::Data<MGState>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let info = data.jobs.cancel(&id).ok_or_else(|| no_such_job(&id))?;
+    Ok(HttpResponse::Ok().json(info))
+}
+
+/*
+Server-sent events, each a JobInfo, starting with the current state
+and ending once the job has finished.
+*/
+#[utoipa::path(
+    tag = "jobs",
+    params(("id" = String, Path, description = "The id of the job")),
+    responses(
+        (status = 200, description = "A stream of JobInfo events", content_type = "text/event-stream", body = JobInfo),
+        (status = 404, description = "No job with the id", body = ErrorResponse),
+    )
+)]
+#[get("/jobs/{id}/events")]
+async fn job_events(
+    data: web::Data<MGState>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let (current, receiver) = data.jobs.subscribe(&id).ok_or_else(|| no_such_job(&id))?;
+
+    let events = stream::unfold(
+        (Some(current), receiver, false),
+        |(next, mut receiver, is_done)| async move {
+            if is_done {
+                return None;
+            }
+            let info = match next {
+                Some(info) => info,
+                None => loop {
+                    match receiver.recv().await {
+                        Ok(info) => break info,
+                        // every event is the whole state, so missed ones don't matter
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => return None,
+                    }
+                },
+            };
+            let json = serde_json::to_string(&info).unwrap_or_default();
+            let event = web::Bytes::from(format!("data: {}\n\n", json));
+            Some((
+                Ok::<_, actix_web::Error>(event),
+                (None, receiver, info.status.is_finished()),
+            ))
+        },
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(events))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(start_build_graph)
+        .service(start_decompose)
+        .service(list_jobs)
+        .service(get_job)
+        .service(cancel_job)
+        .service(job_events);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    async fn wait_for(jobs: &JobRegistry, id: &str) -> JobInfo {
+        let (mut info, mut receiver) = jobs.subscribe(id).unwrap();
+        while !info.status.is_finished() {
+            info = receiver.recv().await.unwrap();
+        }
+        info
+    }
+
+    /* Runs until cancelled, checking between each step. */
+    async fn run_until_cancelled(progress: JobProgress) -> Result<Value, ApiError> {
+        loop {
+            progress.check()?;
+            progress.report(0.5, "Working");
+            actix_web::rt::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn jobs_report_their_result_or_error() {
+        let jobs = JobRegistry::default();
+        let info = jobs.start(JobKind::BuildGraph, |_| async { Ok(json!({"size": 1})) });
+        assert_eq!(info.status, JobStatus::Running);
+        let info = wait_for(&jobs, &info.id).await;
+        assert_eq!(info.status, JobStatus::Completed);
+        assert_eq!(info.progress, 1.0);
+        assert_eq!(info.result, Some(json!({"size": 1})));
+
+        let info = jobs.start(JobKind::Decompose, |_| async {
+            Err(ApiError::Parse(String::from("Invalid grammar")))
+        });
+        let info = wait_for(&jobs, &info.id).await;
+        assert_eq!(info.status, JobStatus::Failed);
+        assert_eq!(info.error.unwrap().code, "PARSE_ERROR");
+    }
+
+    #[actix_web::test]
+    async fn cancelled_jobs_stop_at_their_next_check() {
+        let jobs = JobRegistry::default();
+        let info = jobs.start(JobKind::BuildGraph, run_until_cancelled);
+
+        let cancelling = jobs.cancel(&info.id).unwrap();
+        assert_eq!(cancelling.status, JobStatus::Running);
+        assert_eq!(cancelling.message, "Cancelling");
+
+        let info = wait_for(&jobs, &info.id).await;
+        assert_eq!(info.status, JobStatus::Cancelled);
+        assert!(info.error.is_none());
+        assert!(jobs.cancel("none").is_none());
+    }
+
+    #[actix_web::test]
+    async fn finished_jobs_are_not_cancelled() {
+        let jobs = JobRegistry::default();
+        let info = jobs.start(JobKind::BuildGraph, |_| async { Ok(Value::Null) });
+        wait_for(&jobs, &info.id).await;
+        assert_eq!(jobs.cancel(&info.id).unwrap().status, JobStatus::Completed);
+    }
+
+    #[actix_web::test]
+    async fn only_finished_jobs_are_pruned() {
+        let jobs = JobRegistry::default();
+        let running = jobs.start(JobKind::BuildGraph, run_until_cancelled);
+        for _ in 0..MAX_FINISHED_JOBS + 10 {
+            let info = jobs.start(JobKind::BuildGraph, |_| async { Ok(Value::Null) });
+            wait_for(&jobs, &info.id).await;
+        }
+
+        let list = jobs.list();
+        let finished = list.iter().filter(|j| j.status.is_finished()).count();
+        assert_eq!(finished, MAX_FINISHED_JOBS);
+        assert!(jobs.get(&running.id).is_some());
+        jobs.cancel(&running.id);
+    }
+}
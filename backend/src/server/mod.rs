@@ -0,0 +1,1400 @@
+// src/server/mod.rs
+pub mod auth;
+pub mod config;
+pub mod error;
+pub mod jobs;
+pub mod openapi;
+
+use actix_cors::Cors;
+use actix_web::{
+    get, http::header, middleware, post, web, App, HttpResponse, HttpServer, Responder,
+};
+use parse::parser::Parser;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io;
+use std::sync::Arc;
+use tracing::{debug, error, info, warn};
+use tracing_actix_web::TracingLogger;
+use utoipa::{IntoParams, ToSchema};
+
+use tokio::sync::{Mutex, RwLock};
+
+use crate::{calculator, data, parse};
+
+use auth::ApiToken;
+use calculator::{get_saved_mdl, Calculate, MDL};
+use config::ServerConfig;
+use data::archive::{ArchiveFormat, ConflictPolicy, ImportSummary, MGArchive};
+use data::search::MGQuery;
+use data::secrets::{Secrets, NEO4J_PASSWORD};
+use data::storage::{DataManager, MGDiff, MGExample, MGVersion, Settings};
+use data::store::{save_new_mg, MGStore};
+use error::{ApiError, ErrorResponse};
+use jobs::{JobProgress, JobRegistry};
+use parse::{
+    decomp::{Affix, Decomposer},
+    export::{ExportFormat, Exporter},
+    grammar::Grammar,
+    graph::{GrammarGraph, StateGraph},
+    import::{GrammarFormat, Importer},
+    lint::{LintWarning, Linter, DEFAULT_START_CATEGORY},
+    local_graph::GraphSnapshot,
+    mg::{LexicalItem, MG},
+    reach::{Reachability, ReachabilityReport},
+};
+
+#[utoipa::path(
+    tag = "system",
+    responses(
+        (status = 200, description = "The server is running", body = String, content_type = "text/plain"),
+    )
+)]
+#[get("/health")]
+async fn health_check() -> impl Responder {
+    "Service is up and running!"
+}
+
+#[tracing::instrument(skip_all, fields(items = updated.len()))]
+async fn update_mg(data: &web::Data<MGState>, updated: Vec<LexicalItem>) {
+    // both are locked at once, so no one sees one MG in one and another in the other
+    let mut mg_state = data.mg.lock().await;
+    let mut mg_parser = data.mg_parser.lock().await;
+    *mg_state = updated.clone();
+    mg_parser.update_grammar(updated);
+
+    if let Err(e) = mg_parser.to_json("recent").await {
+        warn!(error = %e, "Unable to save the current MG");
+    }
+}
+
+/* Leaves the current MG as it is, it is only replaced by update_mg. */
+#[tracing::instrument(skip_all)]
+fn parse_new_mg(grammar: &str) -> Result<Vec<LexicalItem>, ApiError> {
+    let mut new_mg = MG::new();
+    Parser::convert_text_to_stored(grammar, &mut new_mg)
+        .map_err(ApiError::parse("Invalid grammar"))?;
+    debug!(items = new_mg.mg.len(), "Parsed grammar");
+    Ok(new_mg.mg)
+}
+
+/*
+Rebuilds the graph from the current MG. Errors from Neo4j are only logged,
+but if the job is cancelled part way the graph is left incomplete and
+Cancelled is returned, until the grammar is built again.
+*/
+#[tracing::instrument(skip_all)]
+async fn update_grammar_graph(
+    data: &web::Data<MGState>,
+    progress: &JobProgress,
+) -> Result<(), ApiError> {
+    let graph_guard = data.graph_db.read().await;
+
+    if let Some(db) = graph_guard.as_ref() {
+        progress.check()?;
+        if let Err(e) = db.clear().await {
+            error!(error = %e, "Unable to clear the graph");
+        }
+
+        // below brackets for sync code only?
+        {
+            let mut mg_parser = data.mg_parser.lock().await;
+
+            let on_item = |done: usize, total: usize, morph: &str| -> Result<(), Box<dyn Error>> {
+                progress.report(
+                    done as f64 / total.max(1) as f64,
+                    &format!("Adding {} to the graph", morph),
+                );
+                Ok(progress.check()?)
+            };
+            match Parser::convert_stored_to_graph_with_progress(&mut mg_parser, db, on_item).await {
+                Ok(()) => info!(items = mg_parser.get_grammar().len(), "Graph updated"),
+                Err(_) if progress.is_cancelled() => {
+                    warn!("Graph build cancelled, the graph is incomplete");
+                    return Err(ApiError::Cancelled(String::from(
+                        "Cancelled while building the graph, which is incomplete until the grammar is built again"
+                    )));
+                }
+                Err(e) => error!(error = %e, "Problem updating the graph"),
+            }
+
+            progress.report(1.0, "Removing redundant states");
+            if let Err(e) = db.remove_redundancy().await {
+                error!(error = %e, "Unable to remove redundant states");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn calculate_size_from_string(
+    grammar: &str,
+    alphabet_size: usize,
+    num_types: usize,
+) -> Result<MDL, ApiError> {
+    let grammar = Grammar::new(grammar, alphabet_size, num_types)
+        .map_err(ApiError::parse("Failed to create Grammar"))?;
+
+    let calculator: calculator::GrammarSizeCalculator = calculator::GrammarSizeCalculator;
+    let size: MDL = calculator.get_grammar_size(&grammar);
+    Ok(size)
+}
+
+#[derive(Serialize, ToSchema)]
+struct GrammarSizeResponse {
+    grammar: String,
+    size: MDL,
+}
+#[derive(Deserialize, ToSchema)]
+struct CalculateSizeInput {
+    alphabet_size: usize,
+    num_types: usize,
+}
+#[utoipa::path(
+    tag = "grammar",
+    responses(
+        (status = 200, description = "The size of the current MG", body = GrammarSizeResponse),
+        (status = 400, description = "The grammar could not be parsed", body = ErrorResponse),
+    )
+)]
+#[post("/calculate-size")]
+async fn request_calculate_size(
+    data: web::Data<MGState>,
+    input: web::Json<CalculateSizeInput>,
+) -> Result<HttpResponse, ApiError> {
+    // calculate the size of the MG
+    // converts to a text representation first
+    let mg_parser = data.mg_parser.lock().await;
+    let mg_as_str = format!("{}", mg_parser);
+    let size: MDL = calculate_size_from_string(&mg_as_str, input.alphabet_size, input.num_types)?;
+
+    let response = GrammarSizeResponse {
+        grammar: mg_as_str,
+        size,
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct GrammarInput {
+    grammar: String,
+    alphabet_size: usize,
+    num_types: usize,
+    start_category: Option<String>,
+}
+#[derive(Serialize, ToSchema)]
+struct BuildMGResponse {
+    grammar: String,
+    size: MDL,
+    warnings: Vec<LintWarning>,
+}
+#[utoipa::path(
+    tag = "grammar",
+    responses(
+        (status = 200, description = "The grammar was parsed and made the current MG", body = BuildMGResponse),
+        (status = 400, description = "The grammar could not be parsed", body = ErrorResponse),
+    )
+)]
+#[post("/build-initial-mg")]
+async fn build_initial_mg(
+    data: web::Data<MGState>,
+    input: web::Json<GrammarInput>,
+) -> Result<HttpResponse, ApiError> {
+    let response = run_build_mg(&data, &input, &JobProgress::none()).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/* Shared with /jobs/build-graph, which runs it in the background. */
+#[tracing::instrument(skip_all)]
+async fn run_build_mg(
+    data: &web::Data<MGState>,
+    input: &GrammarInput,
+    progress: &JobProgress,
+) -> Result<BuildMGResponse, ApiError> {
+    // sized first, so a grammar which can't be sized doesn't replace the current MG
+    progress.report(0.0, "Calculating size");
+    let size: MDL =
+        calculate_size_from_string(&input.grammar, input.alphabet_size, input.num_types)?;
+    progress.check()?;
+    progress.report(0.0, "Parsing grammar");
+    let new_mg = parse_new_mg(&input.grammar)?;
+    let start_category = input
+        .start_category
+        .as_deref()
+        .unwrap_or(DEFAULT_START_CATEGORY);
+    let warnings = Linter::new(start_category).lint(&new_mg);
+
+    // the last point at which cancelling leaves everything as it was
+    progress.check()?;
+    update_mg(data, new_mg).await;
+    update_grammar_graph(data, progress).await?;
+
+    Ok(BuildMGResponse {
+        grammar: input.grammar.clone(),
+        size,
+        warnings,
+    })
+}
+
+// increase whenever linting or reachability change, so older cached results aren't used
+const ANALYSIS_VERSION: u32 = 2;
+
+/* Analyses only depend on the grammar text and start category. */
+fn get_analysis_key(analysis: &str, grammar: &str, start_category: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(grammar.as_bytes());
+    // separated so that a grammar can't run into the start category
+    hasher.update([0]);
+    hasher.update(start_category.as_bytes());
+    let hash: String = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("{}:v{}:{}", analysis, ANALYSIS_VERSION, hash)
+}
+
+async fn get_cached_analysis<T: serde::de::DeserializeOwned>(
+    data: &web::Data<MGState>,
+    key: &str,
+) -> Option<T> {
+    match data.store.get_cached(key).await {
+        Ok(cached) => cached.and_then(|value| serde_json::from_value(value).ok()),
+        Err(e) => {
+            warn!(error = %e, "Unable to read cached analysis");
+            None
+        }
+    }
+}
+
+async fn set_cached_analysis<T: Serialize>(data: &web::Data<MGState>, key: &str, result: &T) {
+    let stored = match serde_json::to_value(result) {
+        Ok(value) => data.store.set_cached(key, &value).await,
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = stored {
+        warn!(error = %e, "Unable to cache analysis");
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+struct LintInput {
+    grammar: String,
+    start_category: Option<String>,
+}
+#[derive(Serialize, Deserialize, ToSchema)]
+struct LintResponse {
+    warnings: Vec<LintWarning>,
+}
+#[utoipa::path(
+    tag = "analysis",
+    responses(
+        (status = 200, description = "Well-formedness warnings", body = LintResponse),
+        (status = 400, description = "The grammar could not be parsed", body = ErrorResponse),
+    )
+)]
+#[post("/lint")]
+async fn lint(
+    data: web::Data<MGState>,
+    input: web::Json<LintInput>,
+) -> Result<HttpResponse, ApiError> {
+    let start_category = input
+        .start_category
+        .as_deref()
+        .unwrap_or(DEFAULT_START_CATEGORY);
+    let cache_key = get_analysis_key("lint", &input.grammar, start_category);
+    if let Some(cached) = get_cached_analysis::<LintResponse>(&data, &cache_key).await {
+        return Ok(HttpResponse::Ok().json(cached));
+    }
+
+    // linting does not touch the current MG
+    let mut mg = MG::new();
+    Parser::convert_text_to_stored(&input.grammar, &mut mg)
+        .map_err(ApiError::parse("Invalid grammar"))?;
+
+    let response = LintResponse {
+        warnings: Linter::new(start_category).lint(mg.get_grammar()),
+    };
+    set_cached_analysis(&data, &cache_key, &response).await;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ReachabilityInput {
+    grammar: String,
+    start_category: Option<String>,
+}
+#[derive(Serialize, Deserialize, ToSchema)]
+struct ReachabilityResponse {
+    report: ReachabilityReport,
+    // the grammar with only its useful items, ready for sizing
+    pruned_grammar: String,
+}
+#[utoipa::path(
+    tag = "analysis",
+    responses(
+        (status = 200, description = "Items which can't be used in a derivation", body = ReachabilityResponse),
+        (status = 400, description = "The grammar could not be parsed", body = ErrorResponse),
+    )
+)]
+#[post("/reachability")]
+async fn reachability(
+    data: web::Data<MGState>,
+    input: web::Json<ReachabilityInput>,
+) -> Result<HttpResponse, ApiError> {
+    let start_category = input
+        .start_category
+        .as_deref()
+        .unwrap_or(DEFAULT_START_CATEGORY);
+    let cache_key = get_analysis_key("reachability", &input.grammar, start_category);
+    if let Some(cached) = get_cached_analysis::<ReachabilityResponse>(&data, &cache_key).await {
+        return Ok(HttpResponse::Ok().json(cached));
+    }
+
+    let mut mg = MG::new();
+    Parser::convert_text_to_stored(&input.grammar, &mut mg)
+        .map_err(ApiError::parse("Invalid grammar"))?;
+
+    let report = Reachability::new(start_category).analyse(mg.get_grammar());
+
+    let useful: Vec<LexicalItem> = report
+        .useful
+        .iter()
+        .map(|i| mg.get_grammar()[*i].clone())
+        .collect();
+    let mut pruned = MG::new();
+    pruned.update_grammar(useful);
+
+    let response = ReachabilityResponse {
+        report,
+        pruned_grammar: format!("{}", pruned),
+    };
+    set_cached_analysis(&data, &cache_key, &response).await;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ImportInput {
+    text: String,
+    // detected from the text if not given
+    format: Option<GrammarFormat>,
+}
+#[derive(Serialize, ToSchema)]
+struct ImportResponse {
+    format: GrammarFormat,
+    grammar: String,
+    items: Vec<LexicalItem>,
+}
+#[utoipa::path(
+    tag = "grammar",
+    responses(
+        (status = 200, description = "The grammar in the native format", body = ImportResponse),
+        (status = 400, description = "The grammar could not be parsed", body = ErrorResponse),
+    )
+)]
+#[post("/import-grammar")]
+async fn import_grammar(input: web::Json<ImportInput>) -> Result<HttpResponse, ApiError> {
+    let (format, grammar, items) = Importer::import(&input.text, input.format)
+        .map_err(ApiError::parse("Grammar could not be imported"))?;
+    Ok(HttpResponse::Ok().json(ImportResponse {
+        format,
+        grammar,
+        items,
+    }))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct ExportQuery {
+    format: ExportFormat,
+}
+#[utoipa::path(
+    tag = "grammar",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "The current MG in the requested format", body = String, content_type = "text/plain"),
+    )
+)]
+#[get("/export-grammar")]
+async fn export_grammar(data: web::Data<MGState>, query: web::Query<ExportQuery>) -> HttpResponse {
+    let mg_parser = data.mg_parser.lock().await;
+    let exported = Exporter::export(mg_parser.get_grammar(), query.format);
+
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(exported)
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CombinationInput {
+    state_a: String,
+    state_b: String,
+}
+#[utoipa::path(
+    tag = "graph",
+    responses(
+        (status = 200, description = "The states were contracted", body = Object),
+        (status = 503, description = "Neo4j is not connected", body = ErrorResponse),
+        (status = 502, description = "A graph query failed", body = ErrorResponse),
+    )
+)]
+#[post("/combine")]
+#[tracing::instrument(skip_all, fields(state_a = %_input.state_a, state_b = %_input.state_b))]
+async fn combine(
+    data: web::Data<MGState>,
+    _input: web::Json<CombinationInput>,
+) -> Result<HttpResponse, ApiError> {
+    let graph_guard = data.graph_db.read().await;
+
+    let graph = graph_guard
+        .as_ref()
+        .ok_or_else(ApiError::graph_unavailable)?;
+    graph
+        .contract_edge(&_input.state_a, &_input.state_b)
+        .await
+        .map_err(ApiError::graph("Contracting failed"))?;
+
+    info!("States contracted");
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "contracted successfully"
+    })))
+}
+
+/*
+#[post("/combine")]
+async fn combine(data: web::Data<MGState>, _input: web::Json<CombinationInput>) -> HttpResponse {
+    let graph_guard = data.graph_db.read().await;
+
+    if let Some(graph) = graph_guard.as_ref() {
+        if let Err(e) = graph.contract_edge(
+            &_input.state_a,
+            &_input.state_b,
+            &_input._li).await {
+            error!(error = %e, "Contracting failed");
+            let err_response = ErrorResponse {
+                error: format!("Contracting failed: {}", e),
+            };
+            return HttpResponse::InternalServerError().json(err_response);
+        }
+    }
+
+    HttpResponse::Ok().finish()
+}
+    */
+
+/*
+NOTE:
+- Doesn't yet work for multiple features. Connections don't connect properly.
+ */
+#[derive(Serialize, Deserialize, ToSchema)]
+struct DecomposeInput {
+    affix: String,
+    split: usize,
+}
+
+#[utoipa::path(
+    tag = "graph",
+    responses(
+        (status = 200, description = "The current MG was decomposed"),
+        (status = 404, description = "The affix wasn't suggested", body = ErrorResponse),
+        (status = 400, description = "The decomposition failed", body = ErrorResponse),
+    )
+)]
+#[post("/decompose")]
+async fn decompose(
+    data: web::Data<MGState>,
+    input: web::Json<DecomposeInput>,
+) -> Result<HttpResponse, ApiError> {
+    run_decompose(&data, &input, &JobProgress::none()).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/* Shared with /jobs/decompose, which runs it in the background. */
+#[tracing::instrument(skip_all, fields(affix = %input.affix, split = input.split))]
+async fn run_decompose(
+    data: &web::Data<MGState>,
+    input: &DecomposeInput,
+    progress: &JobProgress,
+) -> Result<(), ApiError> {
+    let decomposed_mg: Vec<LexicalItem>;
+    progress.report(0.0, "Decomposing");
+    // initial state access
+    {
+        /* We have a function which decomposes the MG, now we need to handle the input. */
+        let mg_state = data.mg.lock().await;
+        let decomposer = data.decomposer.lock().await;
+
+        // suggestions have to be fetched first to find the items to decompose
+        let to_decomp = decomposer
+            .candidate_map
+            .get(&input.affix)
+            .cloned()
+            .ok_or_else(|| {
+                ApiError::NotFound(format!(
+                    "No decomposition suggested for the affix {}",
+                    input.affix
+                ))
+            })?;
+        // let to_decomp = &input.lis_to_decompose;
+
+        let affix: Affix = Affix {
+            morph: input.affix.to_string(),
+        };
+        let split: usize = input.split;
+
+        decomposed_mg = decomposer
+            .decompose(mg_state.to_vec(), to_decomp, affix, split)
+            .map_err(|e| {
+                ApiError::InvalidInput(format!("Could not perform decomposition: {}", e))
+            })?;
+        info!(items = decomposed_mg.len(), "Decomposed");
+    }
+
+    progress.check()?;
+    update_mg(data, decomposed_mg).await;
+    update_grammar_graph(data, progress).await
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct DecomposeSuggestionResponse {
+    prefix_morph_map: HashMap<String, Vec<String>>,
+    test: String,
+}
+#[derive(Deserialize, IntoParams)]
+struct TagQuery {
+    tag: Option<String>,
+}
+#[utoipa::path(
+    tag = "graph",
+    params(TagQuery),
+    responses(
+        (status = 200, description = "Affixes which could be decomposed, with the items they would be taken from", body = DecomposeSuggestionResponse),
+    )
+)]
+#[get("/decompose-suggestions")]
+async fn get_decompose_suggestions(
+    data: web::Data<MGState>,
+    query: web::Query<TagQuery>,
+) -> HttpResponse {
+    let mg_state = data.mg.lock().await;
+
+    // let mut graph = data.graph.lock().await;
+    let mut decomposer = data.decomposer.lock().await;
+
+    let mut suggestions = decomposer.get_decompose_suggestions(&mg_state);
+
+    // only suggest decomposing items with the given tag
+    if let Some(tag) = &query.tag {
+        for lis in suggestions.values_mut() {
+            lis.retain(|i| mg_state.get(*i).is_some_and(|li| li.has_tag(tag)));
+        }
+        suggestions.retain(|_affix, lis| !lis.is_empty());
+        decomposer.candidate_map = suggestions.clone();
+    }
+
+    let suggestions_prefix_morph_map = suggestions
+        .iter()
+        .map(|(x, y)| {
+            let morph_vec = y
+                .iter()
+                .filter_map(|index| mg_state.get(*index).map(|li| li.morph.clone()))
+                .collect::<Vec<_>>(); // Collect morphs into a Vec<String>
+            (x.clone(), morph_vec) // Form (key, value) pair
+        })
+        .collect();
+
+    let response = DecomposeSuggestionResponse {
+        prefix_morph_map: suggestions_prefix_morph_map,
+        test: String::from("Working!"),
+    };
+
+    HttpResponse::Ok().json(response)
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct PathwayResponse {
+    all_pathways: Vec<String>,
+    shortest_pathways: Vec<String>,
+}
+#[derive(Serialize, Deserialize, ToSchema)]
+struct PathwayInput {
+    start_item: String,
+    end_item: String,
+    // only pathways using an item with this tag
+    tag: Option<String>,
+}
+#[utoipa::path(
+    tag = "graph",
+    responses(
+        (status = 200, description = "Paths between two items", body = PathwayResponse),
+        (status = 503, description = "Neo4j is not connected", body = ErrorResponse),
+        (status = 502, description = "A graph query failed", body = ErrorResponse),
+    )
+)]
+#[post("/pathways")]
+async fn pathways(
+    data: web::Data<MGState>,
+    input: web::Json<PathwayInput>,
+) -> Result<HttpResponse, ApiError> {
+    let graph_guard = data.graph_db.read().await;
+    let graph = graph_guard
+        .as_ref()
+        .ok_or_else(ApiError::graph_unavailable)?;
+
+    let mut poss_paths = graph
+        .get_possible_paths(&input.start_item, &input.end_item)
+        .await
+        .map_err(ApiError::graph("Pathways could not be found"))?;
+    let mut shortest_paths = graph
+        .get_shortest_paths(&input.start_item, &input.end_item)
+        .await
+        .map_err(ApiError::graph("Shortest pathways could not be found"))?;
+
+    if let Some(tag) = &input.tag {
+        let tagged: HashSet<String> = data
+            .mg
+            .lock()
+            .await
+            .iter()
+            .filter(|li| li.has_tag(tag))
+            .map(|li| li.morph.clone())
+            .collect();
+        let uses_tagged = |path: &String| path.split(" => ").any(|item| tagged.contains(item));
+        poss_paths.retain(uses_tagged);
+        shortest_paths.retain(uses_tagged);
+    }
+    let response: PathwayResponse = PathwayResponse {
+        all_pathways: poss_paths,
+        shortest_pathways: shortest_paths,
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize)]
+struct SaveMGInput {
+    title: String,
+    lang: String,
+    grammar: Vec<String>,
+}
+
+fn no_such_mg(id: &str) -> ApiError {
+    ApiError::NotFound(format!("No MG with id {}", id))
+}
+
+#[utoipa::path(
+    tag = "collection",
+    responses(
+        (status = 200, description = "The id of the saved MG", body = Object),
+        (status = 500, description = "The saved data could not be read or written", body = ErrorResponse),
+    )
+)]
+#[post("/store-mg")]
+async fn store_mg(
+    data: web::Data<MGState>,
+    input: web::Json<MGExample>,
+) -> Result<HttpResponse, ApiError> {
+    // saving the same grammar twice returns the existing MG
+    let (id, added) = save_new_mg(data.store.as_ref(), input.into_inner())
+        .await
+        .map_err(ApiError::storage("Failed to save MG"))?;
+    let message = if added {
+        "Grammar stored."
+    } else {
+        "Grammar already stored."
+    };
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "id": id,
+        "message": message
+    })))
+}
+
+#[utoipa::path(
+    tag = "collection",
+    responses(
+        (status = 200, description = "The id and new version of the MG", body = Object),
+        (status = 404, description = "No MG with the id", body = ErrorResponse),
+        (status = 500, description = "The saved data could not be read or written", body = ErrorResponse),
+    )
+)]
+#[post("/update-mg")]
+async fn update_saved_mg(
+    data: web::Data<MGState>,
+    input: web::Json<MGExample>,
+) -> Result<HttpResponse, ApiError> {
+    let input = input.into_inner();
+
+    // added by the store in one step, so concurrent updates each get a version
+    let mdl = get_saved_mdl(&input.grammar);
+    let version = data
+        .store
+        .add_version(&input.id, input.title, input.lang, input.grammar, mdl)
+        .await
+        .map_err(ApiError::storage("Failed to save MG"))?
+        .ok_or_else(|| no_such_mg(&input.id))?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "id": input.id,
+        "version": version
+    })))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct MGIdQuery {
+    id: String,
+}
+#[utoipa::path(
+    tag = "collection",
+    params(MGIdQuery),
+    responses(
+        (status = 200, description = "Every saved version, oldest first", body = Vec<MGVersion>),
+        (status = 404, description = "No MG with the id", body = ErrorResponse),
+    )
+)]
+#[get("/mg-history")]
+async fn mg_history(
+    data: web::Data<MGState>,
+    query: web::Query<MGIdQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let mg = data
+        .store
+        .get_mg(&query.id)
+        .await?
+        .ok_or_else(|| no_such_mg(&query.id))?;
+    Ok(HttpResponse::Ok().json(&mg.history))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct MGDiffQuery {
+    id: String,
+    from: usize,
+    to: usize,
+}
+#[utoipa::path(
+    tag = "collection",
+    params(MGDiffQuery),
+    responses(
+        (status = 200, description = "Items added and removed between two versions", body = MGDiff),
+        (status = 404, description = "No MG or version", body = ErrorResponse),
+    )
+)]
+#[get("/diff-mg")]
+async fn diff_mg(
+    data: web::Data<MGState>,
+    query: web::Query<MGDiffQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let mg = data
+        .store
+        .get_mg(&query.id)
+        .await?
+        .ok_or_else(|| no_such_mg(&query.id))?;
+
+    let diff = mg.diff(query.from, query.to).ok_or_else(|| {
+        ApiError::NotFound(format!(
+            "MG {} has no version {} or {}",
+            query.id, query.from, query.to
+        ))
+    })?;
+    Ok(HttpResponse::Ok().json(diff))
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RestoreInput {
+    id: String,
+    version: usize,
+}
+/* Restoring an older version saves it again as the newest version. */
+#[utoipa::path(
+    tag = "collection",
+    responses(
+        (status = 200, description = "The id and new version of the MG", body = Object),
+        (status = 404, description = "No MG or version", body = ErrorResponse),
+        (status = 500, description = "The saved data could not be read or written", body = ErrorResponse),
+    )
+)]
+#[post("/restore-mg")]
+async fn restore_mg(
+    data: web::Data<MGState>,
+    input: web::Json<RestoreInput>,
+) -> Result<HttpResponse, ApiError> {
+    let version = data
+        .store
+        .restore_version(&input.id, input.version)
+        .await
+        .map_err(ApiError::storage("Failed to save MG"))?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!("No version {} of MG {}", input.version, input.id))
+        })?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "id": input.id,
+        "version": version
+    })))
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct GrammarIndex {
+    index: Option<usize>,
+    id: Option<String>,
+}
+#[utoipa::path(
+    tag = "collection",
+    responses(
+        (status = 200, description = "The MG was deleted", body = String, content_type = "text/plain"),
+        (status = 404, description = "No MG with the id or index", body = ErrorResponse),
+        (status = 500, description = "The saved data could not be read or written", body = ErrorResponse),
+    )
+)]
+#[post("/delete-mg")]
+async fn delete_mg(
+    data: web::Data<MGState>,
+    input: web::Json<GrammarIndex>,
+) -> Result<HttpResponse, ApiError> {
+    // ids are preferred, the index is kept for older clients
+    let id = match (&input.id, input.index) {
+        (Some(id), _) => id.clone(),
+        (None, Some(index)) => data
+            .store
+            .load_mg_collection()
+            .await
+            .map_err(ApiError::storage("Failed to read saved MGs"))?
+            .get(index)
+            .map(|mg| mg.id.clone())
+            .ok_or_else(|| ApiError::NotFound(format!("No MG at index {}", index)))?,
+        (None, None) => {
+            return Err(ApiError::InvalidInput(String::from(
+                "Either an id or index is needed.",
+            )))
+        }
+    };
+
+    if !data
+        .store
+        .delete_mg(&id)
+        .await
+        .map_err(ApiError::storage("Failed to delete MG"))?
+    {
+        return Err(no_such_mg(&id));
+    }
+    Ok(HttpResponse::Ok().body("Grammar deleted."))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct ExportCollectionQuery {
+    format: Option<ArchiveFormat>,
+}
+#[utoipa::path(
+    tag = "collection",
+    params(ExportCollectionQuery),
+    responses(
+        (status = 200, description = "An archive of the collection", content(("application/json"), ("application/zip"))),
+        (status = 500, description = "The saved data could not be read or written", body = ErrorResponse),
+    )
+)]
+#[get("/export-collection")]
+async fn export_collection(
+    data: web::Data<MGState>,
+    query: web::Query<ExportCollectionQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let format = query.format.unwrap_or(ArchiveFormat::Json);
+    let archive = MGArchive::from_store(data.store.as_ref())
+        .await
+        .map_err(ApiError::storage("Failed to read saved MGs"))?;
+
+    let (content_type, extension) = match format {
+        ArchiveFormat::Json => ("application/json", "json"),
+        ArchiveFormat::Zip => ("application/zip", "zip"),
+    };
+    let bytes = archive
+        .to_bytes(format)
+        .map_err(ApiError::internal("Failed to create archive"))?;
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"mg-collection.{}\"", extension),
+        ))
+        .body(bytes))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct ImportCollectionQuery {
+    conflict: Option<ConflictPolicy>,
+    // replace the Neo4j settings, other than the password
+    settings: Option<bool>,
+}
+/* The body is an archive from /export-collection, in either format. */
+#[utoipa::path(
+    tag = "collection",
+    params(ImportCollectionQuery),
+    request_body(content = Vec<u8>, description = "An archive from /export-collection", content_type = "application/octet-stream"),
+    responses(
+        (status = 200, description = "What was imported, renamed, overwritten and skipped", body = ImportSummary),
+        (status = 400, description = "The archive could not be read", body = ErrorResponse),
+        (status = 500, description = "The saved data could not be read or written", body = ErrorResponse),
+    )
+)]
+#[post("/import-collection")]
+async fn import_collection(
+    data: web::Data<MGState>,
+    query: web::Query<ImportCollectionQuery>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let archive =
+        MGArchive::from_bytes(&body).map_err(|e| ApiError::InvalidInput(e.to_string()))?;
+
+    let policy = query.conflict.unwrap_or(ConflictPolicy::Skip);
+    let summary = archive
+        .import_into(data.store.as_ref(), policy, query.settings.unwrap_or(false))
+        .await
+        .map_err(ApiError::storage("Failed to import archive"))?;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+struct DBAuth {
+    db_addr: String,
+    db_name: String,
+    username: String,
+    password: String,
+}
+#[utoipa::path(
+    tag = "settings",
+    responses(
+        (status = 200, description = "The settings were saved", body = String, content_type = "text/plain"),
+        (status = 500, description = "The saved data could not be read or written", body = ErrorResponse),
+    )
+)]
+#[post("/store-db-auth")]
+async fn store_db_auth(
+    data: web::Data<MGState>,
+    db_auth: web::Json<DBAuth>,
+) -> Result<HttpResponse, ApiError> {
+    let db_auth = db_auth.into_inner();
+
+    // the UI never receives the password, so an empty one keeps the saved password
+    if !db_auth.password.is_empty() {
+        Secrets::set(NEO4J_PASSWORD, &db_auth.password)
+            .map_err(ApiError::storage("Database password unable to be stored"))?;
+    }
+
+    let settings = Settings {
+        db_addr: db_auth.db_addr,
+        db_name: db_auth.db_name,
+        username: db_auth.username,
+        password: String::new(),
+    };
+    data.store
+        .save_settings(&settings)
+        .await
+        .map_err(ApiError::storage(
+            "Database authentication details unable to be stored",
+        ))?;
+
+    Ok(HttpResponse::Ok().body("Database authentication details stored."))
+}
+
+/*
+Filters, sorts and pages the collection with the query parameters of MGQuery
+e.g ?lang=english&category=c&sort=mdl&limit=20. The number of matches before
+paging is returned in the X-Total-Count header.
+*/
+#[utoipa::path(
+    tag = "collection",
+    params(MGQuery),
+    responses(
+        (status = 200, description = "The matching MGs, with the number before paging in X-Total-Count", body = Vec<MGExample>, headers(("X-Total-Count" = usize, description = "The number of matches before paging"))),
+        (status = 500, description = "The saved data could not be read or written", body = ErrorResponse),
+    )
+)]
+#[get("/load-mg-collection")]
+async fn load_mg_collection(
+    data: web::Data<MGState>,
+    query: web::Query<MGQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let mgs = data
+        .store
+        .load_mg_collection()
+        .await
+        .map_err(ApiError::storage("Failed to read saved MGs"))?;
+    let result = query.search(mgs);
+    Ok(HttpResponse::Ok()
+        .insert_header(("X-Total-Count", result.total.to_string()))
+        .json(result.mgs))
+}
+
+#[utoipa::path(
+    tag = "settings",
+    responses(
+        (status = 200, description = "Connected to Neo4j", body = String, content_type = "text/plain"),
+        (status = 503, description = "Neo4j is not connected", body = ErrorResponse),
+    )
+)]
+#[get("/test-db-auth")]
+async fn test_db_auth(data: web::Data<MGState>) -> Result<HttpResponse, ApiError> {
+    let settings: Settings = load_settings(&data.store)
+        .await
+        .map_err(ApiError::storage("Unable to access settings"))?;
+
+    connect_to_neo4j(
+        data,
+        settings.db_addr.as_str(),
+        settings.db_name.as_str(),
+        settings.username.as_str(),
+        settings.password.as_str(),
+    )
+    .await
+    .map_err(|e| ApiError::GraphUnavailable(format!("Unable to establish connection: {}", e)))?;
+    Ok(HttpResponse::Ok().body("Connected"))
+}
+
+/* Settings with the Neo4j password from the secret store. */
+async fn load_settings(store: &Arc<dyn MGStore>) -> Result<Settings, Box<dyn Error>> {
+    let mut settings = store.load_settings().await?;
+    if settings.password.is_empty() {
+        settings.password = Secrets::get(NEO4J_PASSWORD)?.unwrap_or_default();
+        return Ok(settings);
+    }
+
+    // older versions stored the password in plain text, move it to the secret store
+    Secrets::set(NEO4J_PASSWORD, &settings.password)?;
+    store.save_settings(&settings).await?;
+    if DataManager::load_settings::<Settings>()
+        .await
+        .is_ok_and(|s| !s.password.is_empty())
+    {
+        DataManager::save_settings(&settings).await?;
+    }
+    Ok(settings)
+}
+
+#[derive(Serialize, ToSchema)]
+struct SettingsResponse {
+    // never includes the password
+    #[serde(flatten)]
+    settings: Settings,
+    has_password: bool,
+}
+#[utoipa::path(
+    tag = "settings",
+    responses(
+        (status = 200, description = "The Neo4j settings, without the password", body = SettingsResponse),
+        (status = 500, description = "The saved data could not be read or written", body = ErrorResponse),
+    )
+)]
+#[get("/get-settings")]
+async fn get_settings(data: web::Data<MGState>) -> Result<HttpResponse, ApiError> {
+    let settings = load_settings(&data.store)
+        .await
+        .map_err(ApiError::storage("Settings could not be loaded"))?;
+    let has_password = !settings.password.is_empty();
+    Ok(HttpResponse::Ok().json(SettingsResponse {
+        settings,
+        has_password,
+    }))
+}
+
+/* The visualisation draws this, so the Neo4j credentials never leave the backend. */
+#[utoipa::path(
+    tag = "graph",
+    responses(
+        (status = 200, description = "Every state and edge in the graph", body = GraphSnapshot),
+        (status = 503, description = "Neo4j is not connected", body = ErrorResponse),
+        (status = 502, description = "A graph query failed", body = ErrorResponse),
+    )
+)]
+#[get("/graph")]
+async fn get_graph(data: web::Data<MGState>) -> Result<HttpResponse, ApiError> {
+    let graph_guard = data.graph_db.read().await;
+    let graph = graph_guard
+        .as_ref()
+        .ok_or_else(ApiError::graph_unavailable)?;
+    let snapshot = graph
+        .get_snapshot()
+        .await
+        .map_err(ApiError::graph("The graph could not be read"))?;
+    Ok(HttpResponse::Ok().json(snapshot))
+}
+
+// skips the arguments so the password is never recorded
+#[tracing::instrument(skip_all, fields(address = db_addr))]
+async fn connect_to_neo4j(
+    data: web::Data<MGState>,
+    db_addr: &str,
+    db_name: &str,
+    db_username: &str,
+    db_pw: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut guard = data.graph_db.write().await;
+    if let Some(db) = guard.as_mut() {
+        match db.connect(db_addr, db_name, db_username, db_pw).await {
+            Ok(()) => info!(address = db_addr, "Connected to Neo4j"),
+            Err(e) => {
+                warn!(address = db_addr, error = %e, "Unable to connect to Neo4j");
+                return Err(e);
+            }
+        }
+    } else {
+        let mut new_graph_db = GrammarGraph::new(db_addr, db_name, db_username, db_pw).await?;
+
+        new_graph_db.test_connection().await?;
+        // connecting after starting without Neo4j makes the graph available
+        *guard = Some(new_graph_db);
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    tag = "grammar",
+    responses(
+        (status = 200, description = "The current MG as parsed", body = Object),
+        (status = 500, description = "No MG has been built", body = ErrorResponse),
+    )
+)]
+#[get("/get-mg-json")]
+async fn get_mg_json(data: web::Data<MGState>) -> Result<HttpResponse, ApiError> {
+    let mg_parser = data.mg_parser.lock().await;
+
+    let json = mg_parser
+        .from_json_raw("recent")
+        .map_err(ApiError::internal("Unable to get current MG as JSON"))?;
+    Ok(HttpResponse::Ok().body(json))
+}
+
+const MAX_ARCHIVE_SIZE: usize = 32 * 1024 * 1024;
+// the Tauri webview on each platform
+const ALLOWED_ORIGINS: [&str; 3] = [
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+];
+// the Vite dev server, which release builds don't trust with credentials
+const DEV_ORIGIN: &str = "http://localhost:5173";
+
+fn get_allowed_origins() -> Vec<&'static str> {
+    let dev_origins: &[&str] = if cfg!(debug_assertions) {
+        &[DEV_ORIGIN]
+    } else {
+        &[]
+    };
+    [&ALLOWED_ORIGINS[..], dev_origins].concat()
+}
+
+/* Shared by every request: the working MG, its graph and the saved collection. */
+pub struct MGState {
+    mg: Mutex<Vec<LexicalItem>>,
+    mg_parser: Mutex<MG>,
+    graph_db: RwLock<Option<GrammarGraph>>,
+    decomposer: Mutex<Decomposer>,
+    store: Arc<dyn MGStore>,
+    jobs: JobRegistry,
+}
+
+impl MGState {
+    pub fn new(store: Arc<dyn MGStore>, graph_db: Option<GrammarGraph>) -> Self {
+        Self {
+            mg: Mutex::new(Vec::new()),
+            mg_parser: Mutex::new(MG::new()),
+            graph_db: RwLock::new(graph_db),
+            decomposer: Mutex::new(Decomposer::new()),
+            store,
+            jobs: JobRegistry::default(),
+        }
+    }
+}
+
+/* Registers every route, for use in an App with MGState as app data. */
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg
+        // malformed bodies and queries get the same JSON errors as the handlers
+        .app_data(
+            web::JsonConfig::default()
+                .error_handler(|e, _req| ApiError::InvalidInput(e.to_string()).into()),
+        )
+        .app_data(
+            web::QueryConfig::default()
+                .error_handler(|e, _req| ApiError::InvalidInput(e.to_string()).into()),
+        )
+        .service(request_calculate_size)
+        .service(health_check)
+        .service(decompose)
+        .service(get_decompose_suggestions)
+        .service(build_initial_mg)
+        .service(lint)
+        .service(reachability)
+        .service(import_grammar)
+        .service(export_grammar)
+        .service(combine)
+        .service(pathways)
+        .service(store_mg)
+        .service(update_saved_mg)
+        .service(mg_history)
+        .service(diff_mg)
+        .service(restore_mg)
+        .service(delete_mg)
+        .service(load_mg_collection)
+        .service(export_collection)
+        .service(import_collection)
+        .service(store_db_auth)
+        .service(test_db_auth)
+        .service(get_settings)
+        .service(get_graph)
+        .service(get_mg_json)
+        .service(openapi::get_openapi)
+        .configure(jobs::configure);
+}
+
+/* Connects to Neo4j with the saved settings and serves the API until shut down. */
+pub async fn run(store: Arc<dyn MGStore>, config: ServerConfig) -> io::Result<()> {
+    let mut grammar_graph: Option<GrammarGraph> = None;
+    match load_settings(&store).await {
+        Ok(settings) => {
+            // the password is redacted
+            info!(?settings, "Settings loaded");
+
+            /* connect to the neo4j instance */
+            grammar_graph = match GrammarGraph::new(
+                &settings.db_addr,
+                &settings.db_name,
+                &settings.username,
+                &settings.password,
+            )
+            .await
+            {
+                Ok(g) => Some(g),
+                Err(e) => {
+                    // the graph can be connected later from the settings page
+                    warn!(error = %e, "Unable to connect to Neo4j");
+                    None
+                }
+            };
+            if let Some(ref g) = grammar_graph {
+                if let Err(e) = g.clear().await {
+                    error!(error = %e, "Unable to clear the graph");
+                }
+            }
+        }
+        Err(e) => {
+            error!(error = %e, "Unable to load settings");
+            // Handle the error (retry, fallback, etc.)
+        }
+    }
+
+    let mg_state = web::Data::new(MGState::new(store, grammar_graph));
+    let api_token = ApiToken(config.token.clone());
+    if api_token.0.is_none() {
+        warn!("No API token is set, so any local process can use the API.");
+    }
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(mg_state.clone())
+            // allows importing large archives
+            .app_data(web::PayloadConfig::new(MAX_ARCHIVE_SIZE))
+            .app_data(api_token.clone())
+            // inside CORS, so preflight requests don't need the token
+            .wrap(middleware::from_fn(auth::require_token))
+            .wrap(
+                get_allowed_origins()
+                    .into_iter()
+                    .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+                    .allowed_methods(vec!["GET", "POST"])
+                    .allowed_headers(vec![header::AUTHORIZATION, header::ACCEPT])
+                    .allowed_header(header::CONTENT_TYPE)
+                    .supports_credentials()
+                    .expose_headers(vec!["X-Total-Count"])
+                    .max_age(3600),
+            )
+            // a span for each request, which everything logged while handling it belongs to
+            .wrap(TracingLogger::default())
+            .configure(configure)
+    })
+    .bind((config.host.as_str(), config.port))?
+    .workers(config.workers);
+
+    // the port may have been chosen by the OS
+    for addr in server.addrs() {
+        info!("Listening on http://{}", addr);
+    }
+    server.run().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::sqlite::SqliteStore;
+    use crate::test_utils::{get_temp_dir, use_test_data_dir, DATA_DIR_LOCK};
+    use actix_web::http::StatusCode;
+    use actix_web::test::{
+        call_and_read_body_json, call_service, init_service, read_body_json, TestRequest,
+    };
+
+    #[test]
+    fn dev_origin_is_only_allowed_in_debug_builds() {
+        let origins = get_allowed_origins();
+        assert!(origins.contains(&"tauri://localhost"));
+        assert_eq!(
+            origins.contains(&"http://localhost:5173"),
+            cfg!(debug_assertions)
+        );
+    }
+
+    #[actix_web::test]
+    async fn routes_without_neo4j() {
+        use_test_data_dir();
+        // opening reads the JSON files in the data directory
+        let _guard = DATA_DIR_LOCK.lock().await;
+        let db_dir = get_temp_dir();
+        let store = SqliteStore::open(&db_dir.join(SqliteStore::DB_PATH))
+            .await
+            .unwrap();
+        let state = web::Data::new(MGState::new(Arc::new(store), None));
+        let app = init_service(App::new().app_data(state).configure(configure)).await;
+
+        let res = call_service(&app, TestRequest::get().uri("/health").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // the graph can be connected later, until then it is unavailable
+        let res = call_service(&app, TestRequest::get().uri("/graph").to_request()).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "GRAPH_UNAVAILABLE");
+
+        // malformed bodies get the same JSON errors as the handlers
+        let req = TestRequest::post()
+            .uri("/lint")
+            .set_json(serde_json::json!({"text": "Mary :: d;"}));
+        let res = call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = read_body_json(res).await;
+        assert_eq!(body["code"], "INVALID_INPUT");
+
+        let mg = serde_json::json!({"title": "English", "lang": "en", "grammar": ["Mary :: d;"]});
+        let req = TestRequest::post().uri("/store-mg").set_json(&mg);
+        let stored: serde_json::Value = call_and_read_body_json(&app, req.to_request()).await;
+        let id = stored["id"].as_str().unwrap().to_string();
+
+        let req = TestRequest::get().uri("/load-mg-collection?title=english");
+        let res = call_service(&app, req.to_request()).await;
+        assert_eq!(res.headers().get("X-Total-Count").unwrap(), "1");
+        let mgs: Vec<MGExample> = read_body_json(res).await;
+        assert_eq!(mgs[0].id, id);
+
+        let req = TestRequest::post()
+            .uri("/delete-mg")
+            .set_json(serde_json::json!({"id": id}));
+        assert_eq!(
+            call_service(&app, req.to_request()).await.status(),
+            StatusCode::OK
+        );
+        let req = TestRequest::post()
+            .uri("/delete-mg")
+            .set_json(serde_json::json!({"id": id}));
+        assert_eq!(
+            call_service(&app, req.to_request()).await.status(),
+            StatusCode::NOT_FOUND
+        );
+
+        std::fs::remove_dir_all(db_dir).unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+use super::*;
+use actix_web::{get, HttpResponse};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+/* Every route of the API, for generating clients from /openapi.json. */
+#[derive(OpenApi)]
+#[openapi(
+    modifiers(&BearerAuth),
+    security(("bearer" = [])),
+    info(
+        title = "MG-Graph API",
+        description = "Building, analysing and saving Minimalist Grammars."
+    ),
+    paths(
+        health_check,
+        request_calculate_size,
+        build_initial_mg,
+        lint,
+        reachability,
+        import_grammar,
+        export_grammar,
+        combine,
+        decompose,
+        get_decompose_suggestions,
+        pathways,
+        store_mg,
+        update_saved_mg,
+        mg_history,
+        diff_mg,
+        restore_mg,
+        delete_mg,
+        export_collection,
+        import_collection,
+        store_db_auth,
+        load_mg_collection,
+        test_db_auth,
+        get_settings,
+        get_graph,
+        get_mg_json,
+        jobs::start_build_graph,
+        jobs::start_decompose,
+        jobs::list_jobs,
+        jobs::get_job,
+        jobs::cancel_job,
+        jobs::job_events,
+        get_openapi,
+    ),
+    tags(
+        (name = "grammar", description = "The current MG"),
+        (name = "analysis", description = "Checks which don't change the current MG"),
+        (name = "graph", description = "The state graph in Neo4j"),
+        (name = "collection", description = "Saved MGs and their history"),
+        (name = "settings", description = "The Neo4j connection"),
+        (name = "jobs", description = "Long operations run in the background, with progress events"),
+        (name = "system"),
+    )
+)]
+pub struct ApiDoc;
+
+/* Only needed when the backend was started with a token, /health never needs it. */
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+#[utoipa::path(
+    tag = "system",
+    responses(
+        (status = 200, description = "This document", body = Object),
+    )
+)]
+#[get("/openapi.json")]
+pub async fn get_openapi() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_the_routes_and_errors() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let paths = doc["paths"].as_object().unwrap();
+        for path in ["/health", "/graph", "/load-mg-collection", "/openapi.json"] {
+            assert!(paths.contains_key(path), "{} is missing", path);
+        }
+        assert!(doc["components"]["schemas"]["ErrorResponse"].is_object());
+    }
+}
@@ -0,0 +1,135 @@
+/* Fixtures shared by the tests of several modules. */
+use crate::data::storage::{DataManager, MGExample};
+use crate::data::store::{save_new_mg, MGStore, MAX_CACHED};
+use futures_util::future::join_all;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tokio::sync::Mutex;
+
+/* The data directory is global, so tests which write to it take turns. */
+pub static DATA_DIR_LOCK: Mutex<()> = Mutex::const_new(());
+
+pub fn lines(grammar: &[&str]) -> Vec<String> {
+    grammar.iter().map(|l| l.to_string()).collect()
+}
+
+/* An unsaved English MG without any history. */
+pub fn get_mg(title: &str, grammar: &[&str]) -> MGExample {
+    MGExample {
+        id: String::new(),
+        title: title.to_string(),
+        lang: String::from("en"),
+        grammar: lines(grammar),
+        history: Vec::new(),
+    }
+}
+
+/* A fresh directory, removed by the test that asked for it. */
+pub fn get_temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mggraph-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/* Points the data directory at an empty temporary one, shared by every test. */
+pub fn use_test_data_dir() -> &'static Path {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    let dir = DIR.get_or_init(|| {
+        let dir = get_temp_dir();
+        DataManager::set_data_dir(dir.clone());
+        dir
+    });
+    // nothing may have read the real data directory first
+    assert_eq!(DataManager::get_data_dir(), dir);
+    dir
+}
+
+/* The behaviour every store must share, whatever it already holds. */
+pub async fn check_store(store: &dyn MGStore) {
+    let n_saved = store.load_mg_collection().await.unwrap().len();
+    // the same grammar is only saved once
+    let (id, is_added) = save_new_mg(store, get_mg("A", &["Mary :: d;"]))
+        .await
+        .unwrap();
+    assert!(is_added);
+    let (same_id, is_added) = save_new_mg(store, get_mg("A", &["Mary :: d;"]))
+        .await
+        .unwrap();
+    assert_eq!((same_id.as_str(), is_added), (id.as_str(), false));
+    let (other_id, _) = save_new_mg(store, get_mg("B", &["John :: d;"]))
+        .await
+        .unwrap();
+    assert_ne!(other_id, id);
+
+    // versions follow on from each other, and restoring adds one
+    let grammar = vec![String::from("Mary :: d -k;")];
+    let version = store.add_version(
+        &id,
+        String::from("A"),
+        String::from("en"),
+        grammar,
+        Some(1.0),
+    );
+    assert_eq!(version.await.unwrap(), Some(2));
+    assert_eq!(store.restore_version(&id, 1).await.unwrap(), Some(3));
+    let mg = store.get_mg(&id).await.unwrap().unwrap();
+    assert_eq!(mg.grammar, vec!["Mary :: d;"]);
+    assert_eq!(
+        mg.history.iter().map(|v| v.version).collect::<Vec<usize>>(),
+        vec![1, 2, 3]
+    );
+
+    assert_eq!(store.restore_version(&id, 9).await.unwrap(), None);
+    assert_eq!(store.restore_version("none", 1).await.unwrap(), None);
+    let missing = store.add_version("none", String::new(), String::new(), Vec::new(), None);
+    assert_eq!(missing.await.unwrap(), None);
+
+    // concurrent saves each get their own version
+    let saves = (0..20).map(|i| {
+        store.add_version(
+            &id,
+            String::from("A"),
+            String::from("en"),
+            vec![i.to_string()],
+            None,
+        )
+    });
+    let mut versions: Vec<usize> = join_all(saves)
+        .await
+        .into_iter()
+        .map(|v| v.unwrap().unwrap())
+        .collect();
+    versions.sort();
+    assert_eq!(versions, (4..24).collect::<Vec<usize>>());
+
+    // MGs from before versioning keep their content as version 1
+    let mut legacy = get_mg("C", &["cat :: n;"]);
+    legacy.ensure_id();
+    store.save_mg(&legacy).await.unwrap();
+    let version = store.add_version(
+        &legacy.id,
+        String::from("C"),
+        String::from("en"),
+        Vec::new(),
+        None,
+    );
+    assert_eq!(version.await.unwrap(), Some(2));
+    let legacy = store.get_mg(&legacy.id).await.unwrap().unwrap();
+    assert_eq!(legacy.get_version(1).unwrap().grammar, vec!["cat :: n;"]);
+
+    assert!(store.delete_mg(&other_id).await.unwrap());
+    assert!(!store.delete_mg(&other_id).await.unwrap());
+    assert_eq!(store.load_mg_collection().await.unwrap().len(), n_saved + 2);
+
+    // the newest cached result is kept when the oldest are removed
+    for i in 0..=MAX_CACHED {
+        store.set_cached(&i.to_string(), &json!(i)).await.unwrap();
+    }
+    let newest = MAX_CACHED.to_string();
+    assert_eq!(
+        store.get_cached(&newest).await.unwrap(),
+        Some(json!(MAX_CACHED))
+    );
+    assert_eq!(store.get_cached("none").await.unwrap(), None);
+}
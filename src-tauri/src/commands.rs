@@ -0,0 +1,107 @@
+/*
+The stateless parts of the engine, called directly from the UI.
+Everything which reads or writes the data directory, the current MG
+or Neo4j is served by the sidecar, so only one process opens the store.
+*/
+
+use serde::Serialize;
+
+use mggraph_backend::calculator::{Calculate, GrammarSizeCalculator, MDL};
+use mggraph_backend::parse::grammar::Grammar;
+use mggraph_backend::parse::import::{GrammarFormat, Importer};
+use mggraph_backend::parse::lint::{LintWarning, Linter, DEFAULT_START_CATEGORY};
+use mggraph_backend::parse::mg::{LexicalItem, MG};
+use mggraph_backend::parse::parser::Parser;
+use mggraph_backend::parse::reach::{Reachability, ReachabilityReport};
+use mggraph_backend::server::error::{ApiError, ErrorResponse};
+
+// failures have the same {"code", "error"} shape as the HTTP API
+type CommandResult<T> = Result<T, ErrorResponse>;
+
+fn parse_grammar(grammar: &str) -> Result<MG, ApiError> {
+    let mut mg = MG::new();
+    Parser::convert_text_to_stored(grammar, &mut mg).map_err(ApiError::parse("Invalid grammar"))?;
+    Ok(mg)
+}
+
+#[tauri::command]
+pub fn calculate_size(
+    grammar: String,
+    alphabet_size: usize,
+    num_types: usize,
+) -> CommandResult<MDL> {
+    let grammar = Grammar::new(&grammar, alphabet_size, num_types)
+        .map_err(ApiError::parse("Failed to create Grammar"))?;
+    Ok(GrammarSizeCalculator.get_grammar_size(&grammar))
+}
+
+#[tauri::command]
+pub fn lint_grammar(
+    grammar: String,
+    start_category: Option<String>,
+) -> CommandResult<Vec<LintWarning>> {
+    let mg = parse_grammar(&grammar)?;
+    let start_category = start_category.as_deref().unwrap_or(DEFAULT_START_CATEGORY);
+    Ok(Linter::new(start_category).lint(mg.get_grammar()))
+}
+
+#[tauri::command]
+pub fn check_reachability(
+    grammar: String,
+    start_category: Option<String>,
+) -> CommandResult<ReachabilityReport> {
+    let mg = parse_grammar(&grammar)?;
+    let start_category = start_category.as_deref().unwrap_or(DEFAULT_START_CATEGORY);
+    Ok(Reachability::new(start_category).analyse(mg.get_grammar()))
+}
+
+#[derive(Serialize)]
+pub struct ImportedGrammar {
+    format: GrammarFormat,
+    grammar: String,
+    items: Vec<LexicalItem>,
+}
+
+#[tauri::command]
+pub fn import_grammar(
+    text: String,
+    format: Option<GrammarFormat>,
+) -> CommandResult<ImportedGrammar> {
+    let (format, grammar, items) = Importer::import(&text, format)
+        .map_err(ApiError::parse("Grammar could not be imported"))?;
+    Ok(ImportedGrammar {
+        format,
+        grammar,
+        items,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_use_the_engine() {
+        let report = check_reachability(
+            String::from("Mary :: d;\nsmiles :: =d c;\nJohn :: x;"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(report.useful, vec![0, 1]);
+        assert_eq!(report.dead.len(), 1);
+
+        let imported = import_grammar(String::from("[mary]::[d,-k]."), None).unwrap();
+        assert_eq!(imported.format, GrammarFormat::Prolog);
+        assert_eq!(imported.grammar, "mary :: d -k;\n");
+    }
+
+    #[test]
+    fn failures_have_the_same_codes_as_the_api() {
+        let text = r#"[{"phon": "Mary", "features": [["licensee", "k"]]}]"#;
+        let error = import_grammar(String::from(text), Some(GrammarFormat::Json))
+            .err()
+            .unwrap();
+        assert_eq!(error.code, "PARSE_ERROR");
+        assert!(error.error.starts_with("Grammar could not be imported"));
+    }
+}
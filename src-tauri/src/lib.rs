@@ -1,65 +1,106 @@
-use tauri::App;
-use tauri_plugin_shell::ShellExt;
-use std::sync::{Arc, Mutex};
-use tauri_plugin_shell::process::CommandChild;
-use std::path::{PathBuf};
+mod commands;
+mod sidecar;
+
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::Manager;
+
+use sidecar::{BackendStatus, Sidecar};
+
+const BACKEND_HOST: &str = "127.0.0.1";
+// used if no free port can be found
+const DEFAULT_BACKEND_PORT: u16 = 8000;
+
+/* Where the frontend should send requests. */
+struct BackendUrl(String);
+
+/* Sent with every request, so nothing else on the machine can use the backend. New on every launch. */
+struct BackendToken(String);
+
+/* Asks the OS for a port nothing is listening on. */
+fn find_free_port() -> u16 {
+    TcpListener::bind((BACKEND_HOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .unwrap_or(DEFAULT_BACKEND_PORT)
+}
+
+#[tauri::command]
+fn get_backend_url(backend_url: tauri::State<BackendUrl>) -> String {
+    backend_url.0.clone()
+}
+
+#[tauri::command]
+fn get_backend_token(backend_token: tauri::State<BackendToken>) -> String {
+    backend_token.0.clone()
+}
+
+/* The current status, as the UI may have missed the events sent before it loaded. */
+#[tauri::command]
+fn get_backend_status(sidecar: tauri::State<Arc<Sidecar>>) -> BackendStatus {
+    sidecar.status()
+}
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let sidecar_process: Arc<Mutex<Option<CommandChild>>> = Arc::new(Mutex::new(None));
-    let sidecar_setup = Arc::clone(&sidecar_process);
-    let sidecar_exit = Arc::clone(&sidecar_process);
-
     let mut backend_path = PathBuf::from("bin/mggraph-backend");
     #[cfg(target_os = "windows")]
     backend_path.set_extension("exe");
 
+    let backend_port = find_free_port();
+    let backend_token = uuid::Uuid::new_v4().simple().to_string();
+    let sidecar = Arc::new(Sidecar::new(
+        backend_path,
+        BACKEND_HOST,
+        backend_port,
+        &backend_token,
+    ));
+    let sidecar_setup = Arc::clone(&sidecar);
+    let sidecar_exit = Arc::clone(&sidecar);
+
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_shell::init())
+        .manage(BackendUrl(format!(
+            "http://{}:{}",
+            BACKEND_HOST, backend_port
+        )))
+        .manage(BackendToken(backend_token))
+        .manage(Arc::clone(&sidecar))
+        .invoke_handler(tauri::generate_handler![
+            get_backend_url,
+            get_backend_token,
+            get_backend_status,
+            commands::calculate_size,
+            commands::lint_grammar,
+            commands::check_reachability,
+            commands::import_grammar,
+        ])
         .setup(move |app| {
-            if cfg!(debug_assertions) {
-                app.handle().plugin(
-                    tauri_plugin_log::Builder::default()
-                        .level(log::LevelFilter::Info)
-                        .build(),
-                )?;
-            }
-        
-            start_backend(backend_path, app, &sidecar_setup);
+            // also collects the backend's output
+            app.handle().plugin(
+                tauri_plugin_log::Builder::default()
+                    .level(log::LevelFilter::Info)
+                    .build(),
+            )?;
+
+            // the saved collection, the current MG and the graph are served by the sidecar
+            sidecar_setup.start(app.handle().clone());
 
             Ok(())
         })
-        .on_window_event(move |window, event| match event {
-            tauri::WindowEvent::CloseRequested { .. } => {
-                exit_backend(&sidecar_exit);
-            },
-            _ => {}
+        .on_window_event(move |_window, event| {
+            if let tauri::WindowEvent::CloseRequested { .. } = event {
+                sidecar_exit.stop();
+            }
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
-
-}
-
-#[tauri::command]
-fn start_backend(backend_path: PathBuf, app: &mut App, sidecar_process: &Arc<Mutex<Option<CommandChild>>>) {
-
-    // #[cfg(target_os = "windows")]
-    // path.set_extension("exe");
-
-    let sidecar_command = app.shell().sidecar(backend_path).unwrap();
-    let (mut rx, mut _child) = sidecar_command
-    .spawn()
-    .expect("Failed to spawn sidecar");
-
-    *sidecar_process.lock().unwrap() = Some(_child);
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(move |_app, event| {
+            // also covers quitting without closing the window
+            if let tauri::RunEvent::Exit = event {
+                sidecar.stop();
+            }
+        });
 }
-
-#[tauri::command]
-fn exit_backend(sidecar_process: &Arc<Mutex<Option<CommandChild>>>) {
-    if let Some(child) = sidecar_process.lock().unwrap().take() {
-        if let Err(e) = child.kill() {
-            eprintln!("{}", e);
-        }
-    }
-}
\ No newline at end of file
@@ -0,0 +1,322 @@
+use serde::Serialize;
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::async_runtime::Receiver;
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_shell::process::{CommandChild, CommandEvent};
+use tauri_plugin_shell::ShellExt;
+
+// sent to the UI whenever the state of the backend changes
+const STATUS_EVENT: &str = "backend-status";
+// the backend's output is logged under this target
+const LOG_TARGET: &str = "backend";
+// read by the backend, rather than passed as an argument which other users could see
+const TOKEN_ENV: &str = "MGGRAPH_API_TOKEN";
+// the backend logs one JSON object per line, so each keeps its level here
+const LOG_FORMAT_ENV: &str = "MGGRAPH_LOG_FORMAT";
+
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+const READY_POLL: Duration = Duration::from_millis(250);
+const FIRST_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+// crashes in a row before giving up
+const MAX_RESTARTS: u32 = 5;
+// a backend which stayed up this long is no longer counted as crashing
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendState {
+    Starting,
+    Ready,
+    Restarting,
+    Failed,
+    Stopped,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BackendStatus {
+    state: BackendState,
+    message: String,
+}
+
+/*
+Runs the HTTP backend as a child process, restarting it with
+increasing delays if it exits without being asked to.
+*/
+pub struct Sidecar {
+    path: PathBuf,
+    host: String,
+    port: u16,
+    token: String,
+    child: Mutex<Option<CommandChild>>,
+    status: Mutex<BackendStatus>,
+    stopping: AtomicBool,
+    // incremented on every start, so a readiness check for an earlier process is ignored
+    generation: AtomicU64,
+}
+
+impl Sidecar {
+    pub fn new(path: PathBuf, host: &str, port: u16, token: &str) -> Self {
+        Self {
+            path,
+            host: host.to_string(),
+            port,
+            token: token.to_string(),
+            child: Mutex::new(None),
+            status: Mutex::new(BackendStatus {
+                state: BackendState::Starting,
+                message: String::from("Starting the backend."),
+            }),
+            stopping: AtomicBool::new(false),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /* Starts the backend and keeps it running until stop is called. */
+    pub fn start(self: &Arc<Self>, app: AppHandle) {
+        let sidecar = Arc::clone(self);
+        thread::spawn(move || sidecar.supervise(app));
+    }
+
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        if let Some(child) = self.lock_child().take() {
+            if let Err(e) = child.kill() {
+                log::error!(target: LOG_TARGET, "Unable to stop the backend: {}", e);
+            }
+        }
+    }
+
+    pub fn status(&self) -> BackendStatus {
+        self.lock_status().clone()
+    }
+
+    fn lock_child(&self) -> MutexGuard<'_, Option<CommandChild>> {
+        self.child.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_status(&self) -> MutexGuard<'_, BackendStatus> {
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_status(&self, app: &AppHandle, state: BackendState, message: &str) {
+        let status = BackendStatus {
+            state,
+            message: message.to_string(),
+        };
+        match state {
+            BackendState::Failed | BackendState::Restarting => {
+                log::error!(target: LOG_TARGET, "{}", message)
+            }
+            _ => log::info!(target: LOG_TARGET, "{}", message),
+        }
+        *self.lock_status() = status.clone();
+        if let Err(e) = app.emit(STATUS_EVENT, status) {
+            log::error!(target: LOG_TARGET, "Unable to send the backend status: {}", e);
+        }
+    }
+
+    fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    fn supervise(self: Arc<Self>, app: AppHandle) {
+        let mut crashes: u32 = 0;
+        loop {
+            self.set_status(&app, BackendState::Starting, "Starting the backend.");
+            let started = Instant::now();
+            let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+
+            let exit = match self.spawn(&app) {
+                Ok(events) => {
+                    let sidecar = Arc::clone(&self);
+                    let handle = app.clone();
+                    thread::spawn(move || sidecar.wait_until_ready(&handle, generation));
+                    self.forward_output(events)
+                }
+                Err(e) => format!("Unable to start the backend: {}.", e),
+            };
+            self.lock_child().take();
+
+            if self.is_stopping() {
+                self.set_status(&app, BackendState::Stopped, "The backend was stopped.");
+                return;
+            }
+
+            if started.elapsed() >= STABLE_AFTER {
+                crashes = 0;
+            }
+            crashes += 1;
+            if crashes > MAX_RESTARTS {
+                let message = format!("{} Gave up after {} restarts.", exit, MAX_RESTARTS);
+                self.set_status(&app, BackendState::Failed, &message);
+                return;
+            }
+
+            let backoff = get_backoff(crashes);
+            let message = format!("{} Restarting in {:.1}s.", exit, backoff.as_secs_f32());
+            self.set_status(&app, BackendState::Restarting, &message);
+            thread::sleep(backoff);
+        }
+    }
+
+    fn spawn(&self, app: &AppHandle) -> Result<Receiver<CommandEvent>, Box<dyn Error>> {
+        let port = self.port.to_string();
+        let (events, child) = app
+            .shell()
+            .sidecar(&self.path)?
+            .args(["--host", self.host.as_str(), "--port", port.as_str()])
+            .env(TOKEN_ENV, &self.token)
+            .env(LOG_FORMAT_ENV, "json")
+            .spawn()?;
+
+        let mut current = self.lock_child();
+        // stop may have been called while starting
+        if self.is_stopping() {
+            child.kill()?;
+        } else {
+            *current = Some(child);
+        }
+        Ok(events)
+    }
+
+    /* Logs everything the backend prints, returning why it exited. */
+    fn forward_output(&self, mut events: Receiver<CommandEvent>) -> String {
+        while let Some(event) = events.blocking_recv() {
+            match event {
+                CommandEvent::Stdout(line) => {
+                    forward_line(String::from_utf8_lossy(&line).trim_end())
+                }
+                CommandEvent::Stderr(line) => {
+                    log::warn!(target: LOG_TARGET, "{}", String::from_utf8_lossy(&line).trim_end());
+                }
+                CommandEvent::Error(e) => log::error!(target: LOG_TARGET, "{}", e),
+                CommandEvent::Terminated(payload) => {
+                    return match payload.code {
+                        Some(code) => format!("The backend exited with code {}.", code),
+                        None => String::from("The backend was killed."),
+                    };
+                }
+                _ => {}
+            }
+        }
+        String::from("Lost contact with the backend.")
+    }
+
+    fn wait_until_ready(&self, app: &AppHandle, generation: u64) {
+        let deadline = Instant::now() + READY_TIMEOUT;
+        while Instant::now() < deadline {
+            // the process this was started for has already exited
+            if self.is_stopping() || self.generation.load(Ordering::SeqCst) != generation {
+                return;
+            }
+            if self.is_healthy() {
+                self.set_status(app, BackendState::Ready, "The backend is ready.");
+                return;
+            }
+            thread::sleep(READY_POLL);
+        }
+        log::warn!(target: LOG_TARGET, "The backend did not answer /health within {}s.", READY_TIMEOUT.as_secs());
+    }
+
+    /* A plain request to /health, to avoid an HTTP client just for this. */
+    fn is_healthy(&self) -> bool {
+        let Ok(addr) = format!("{}:{}", self.host, self.port).parse::<SocketAddr>() else {
+            return false;
+        };
+        let check = || -> std::io::Result<bool> {
+            let mut stream = TcpStream::connect_timeout(&addr, READY_POLL)?;
+            stream.set_read_timeout(Some(READY_POLL))?;
+            write!(stream, "GET /health HTTP/1.0\r\nHost: {}\r\n\r\n", addr)?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response.split_whitespace().nth(1) == Some("200"))
+        };
+        check().unwrap_or(false)
+    }
+}
+
+/* Doubles from FIRST_BACKOFF with each crash in a row, up to MAX_BACKOFF. */
+fn get_backoff(crashes: u32) -> Duration {
+    // capped so the shift can't overflow, MAX_BACKOFF is reached long before
+    let doublings = crashes.saturating_sub(1).min(16);
+    FIRST_BACKOFF
+        .saturating_mul(1 << doublings)
+        .min(MAX_BACKOFF)
+}
+
+/*
+Logs a line of the backend's JSON output at its own level, e.g
+{"level":"WARN","fields":{"message":"..."},"target":"mggraph_backend::parse"}.
+Anything else, such as a panic, is logged as it is.
+*/
+fn forward_line(line: &str) {
+    let Ok(entry) = serde_json::from_str::<serde_json::Value>(line) else {
+        log::info!(target: LOG_TARGET, "{}", line);
+        return;
+    };
+    let level = match entry["level"].as_str() {
+        Some("ERROR") => log::Level::Error,
+        Some("WARN") => log::Level::Warn,
+        Some("DEBUG") => log::Level::Debug,
+        Some("TRACE") => log::Level::Trace,
+        _ => log::Level::Info,
+    };
+    let source = entry["target"].as_str().unwrap_or_default();
+    let mut fields = entry["fields"].as_object().cloned().unwrap_or_default();
+    let message = fields
+        .remove("message")
+        .and_then(|m| m.as_str().map(String::from))
+        .unwrap_or_default();
+    if fields.is_empty() {
+        log::log!(target: LOG_TARGET, level, "{}: {}", source, message);
+    } else {
+        log::log!(target: LOG_TARGET, level, "{}: {} {}", source, message, serde_json::Value::Object(fields));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit() {
+        assert_eq!(get_backoff(1), FIRST_BACKOFF);
+        assert_eq!(get_backoff(2), FIRST_BACKOFF * 2);
+        assert_eq!(get_backoff(MAX_RESTARTS), FIRST_BACKOFF * 16);
+        assert_eq!(get_backoff(40), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn only_a_200_from_health_is_ready() {
+        let respond = |status: &'static str| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 256];
+                let _ = stream.read(&mut request);
+                let _ = write!(stream, "HTTP/1.0 {}\r\n\r\n", status);
+            });
+            Sidecar::new(PathBuf::new(), "127.0.0.1", port, "").is_healthy()
+        };
+        assert!(respond("200 OK"));
+        assert!(!respond("401 Unauthorized"));
+        // nothing listening
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        assert!(!Sidecar::new(PathBuf::new(), "127.0.0.1", port, "").is_healthy());
+        assert!(!Sidecar::new(PathBuf::new(), "localhost", port, "").is_healthy());
+    }
+}
@@ -1,6 +1,8 @@
 /// <reference types="vite/client" />
 interface ImportMetaEnv {
   readonly VITE_NEO_PW: string; 
+  readonly VITE_BACKEND_URL?: string;
+  readonly VITE_BACKEND_TOKEN?: string;
 }
 
 interface ImportMeta {
@@ -0,0 +1,64 @@
+// used when running in a browser without the Tauri shell, e.g with `npm run dev`
+const DEFAULT_BACKEND_URL = import.meta.env.VITE_BACKEND_URL || "http://127.0.0.1:8000";
+
+let backendUrl: Promise<string> | null = null;
+let backendToken: Promise<string | null> | null = null;
+
+/* True inside the desktop app, where the stateless engine commands can be called directly. Saved MGs always go through the backend. */
+export function isTauri(): boolean {
+    return (window as any).__TAURI__ !== undefined;
+}
+
+/* Calls a Tauri command, which fails with the same {code, error} as the HTTP API. */
+export function invoke<T>(command: string, args: Record<string, unknown> = {}): Promise<T> {
+    return (window as any).__TAURI__.core.invoke(command, args);
+}
+
+/* The address of the backend, which the Tauri shell starts on a free port. */
+export function getBackendUrl(): Promise<string> {
+    if (backendUrl === null) {
+        backendUrl = isTauri()
+            ? invoke<string>("get_backend_url").catch(() => DEFAULT_BACKEND_URL)
+            : Promise.resolve(DEFAULT_BACKEND_URL);
+    }
+    return backendUrl as Promise<string>;
+}
+
+/* e.g await backend("/health") */
+export async function backend(path: string): Promise<string> {
+    return `${await getBackendUrl()}${path}`;
+}
+
+/* The secret the backend was started with, if any. The desktop app makes a new one on every launch. */
+function getBackendToken(): Promise<string | null> {
+    if (backendToken === null) {
+        backendToken = isTauri()
+            ? invoke<string>("get_backend_token").catch(() => null)
+            : Promise.resolve(import.meta.env.VITE_BACKEND_TOKEN || null);
+    }
+    return backendToken;
+}
+
+/* fetch for a backend route, sending the API token. */
+export async function apiFetch(path: string, init: RequestInit = {}): Promise<Response> {
+    const headers = new Headers(init.headers);
+    const token = await getBackendToken();
+    if (token) {
+        headers.set("Authorization", `Bearer ${token}`);
+    }
+    return fetch(await backend(path), { ...init, headers });
+}
+
+export interface BackendStatus {
+    state: "starting" | "ready" | "restarting" | "failed" | "stopped";
+    message: string;
+}
+
+/* Calls back with the backend's current status and whenever it changes. Only the desktop app supervises the backend. */
+export async function onBackendStatus(callback: (status: BackendStatus) => void): Promise<void> {
+    if (!isTauri()) {
+        return;
+    }
+    await (window as any).__TAURI__.event.listen("backend-status", (event: { payload: BackendStatus }) => callback(event.payload));
+    callback(await invoke<BackendStatus>("get_backend_status"));
+}